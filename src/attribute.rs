@@ -0,0 +1,279 @@
+use rusoto_dynamodb::AttributeValue;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Number, Value};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Why a DynamoDB item could not be mapped to or from a Rust value.
+#[derive(Debug)]
+pub struct AttributeError {
+    /// Dotted path of the offending attribute, e.g. `ItemSearchCategory.ID`.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for AttributeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() || self.path == "." {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+fn join_path(parent: &str, key: &str) -> String {
+    if parent.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", parent, key)
+    }
+}
+
+fn attribute_to_json(path: &str, attr: &AttributeValue) -> Result<Value, AttributeError> {
+    let number = |text: &str| -> Result<Value, AttributeError> {
+        let parsed = match text.parse::<u64>() {
+            Ok(n) => Some(Number::from(n)),
+            Err(_) => match text.parse::<i64>() {
+                Ok(n) => Some(Number::from(n)),
+                Err(_) => text.parse::<f64>().ok().and_then(Number::from_f64),
+            },
+        };
+        match parsed {
+            None => Err(AttributeError {
+                path: path.to_string(),
+                message: format!("'{}' is not a number", text),
+            }),
+            Some(n) => Ok(Value::Number(n)),
+        }
+    };
+
+    if let Some(s) = &attr.s {
+        return Ok(Value::String(s.clone()));
+    }
+    if let Some(n) = &attr.n {
+        return number(n);
+    }
+    if let Some(b) = attr.bool {
+        return Ok(Value::Bool(b));
+    }
+    if attr.null == Some(true) {
+        return Ok(Value::Null);
+    }
+    if let Some(m) = &attr.m {
+        return map_to_json(path, m).map(Value::Object);
+    }
+    if let Some(l) = &attr.l {
+        let mut values: Vec<Value> = Vec::new();
+        for (i, attr) in l.iter().enumerate() {
+            match attribute_to_json(&join_path(path, &i.to_string()), attr) {
+                Err(e) => return Err(e),
+                Ok(value) => values.push(value),
+            }
+        }
+        return Ok(Value::Array(values));
+    }
+    if let Some(ss) = &attr.ss {
+        return Ok(Value::Array(
+            ss.iter().cloned().map(Value::String).collect(),
+        ));
+    }
+    if let Some(ns) = &attr.ns {
+        let mut values: Vec<Value> = Vec::new();
+        for n in ns {
+            match number(n) {
+                Err(e) => return Err(e),
+                Ok(value) => values.push(value),
+            }
+        }
+        return Ok(Value::Array(values));
+    }
+    Err(AttributeError {
+        path: path.to_string(),
+        message: "attribute type is not supported".to_string(),
+    })
+}
+
+fn map_to_json(
+    path: &str,
+    map: &HashMap<String, AttributeValue>,
+) -> Result<Map<String, Value>, AttributeError> {
+    let mut object = Map::new();
+    for (key, attr) in map {
+        match attribute_to_json(&join_path(path, key), attr) {
+            Err(e) => return Err(e),
+            Ok(value) => {
+                object.insert(key.clone(), value);
+            }
+        }
+    }
+    Ok(object)
+}
+
+/// `None` for JSON nulls, which are left out of the item instead of stored as `NULL`.
+fn json_to_attribute(value: Value) -> Option<AttributeValue> {
+    let attr = match value {
+        Value::Null => return None,
+        Value::Bool(b) => AttributeValue {
+            bool: Some(b),
+            ..Default::default()
+        },
+        Value::Number(n) => AttributeValue {
+            n: Some(n.to_string()),
+            ..Default::default()
+        },
+        Value::String(s) => AttributeValue {
+            s: Some(s),
+            ..Default::default()
+        },
+        Value::Array(values) => AttributeValue {
+            l: Some(values.into_iter().filter_map(json_to_attribute).collect()),
+            ..Default::default()
+        },
+        Value::Object(object) => AttributeValue {
+            m: Some(json_to_map(object)),
+            ..Default::default()
+        },
+    };
+    Some(attr)
+}
+
+fn json_to_map(object: Map<String, Value>) -> HashMap<String, AttributeValue> {
+    object
+        .into_iter()
+        .filter_map(|(key, value)| json_to_attribute(value).map(|attr| (key, attr)))
+        .collect()
+}
+
+/// Maps a DynamoDB item onto `T` through its serde `Deserialize` implementation, so
+/// attribute names follow the `#[serde(rename)]`s of `T`.
+pub fn from_attributes<T: DeserializeOwned>(
+    item: &HashMap<String, AttributeValue>,
+) -> Result<T, AttributeError> {
+    let value = match map_to_json("", item) {
+        Err(e) => return Err(e),
+        Ok(object) => Value::Object(object),
+    };
+    match serde_path_to_error::deserialize(value) {
+        Err(e) => Err(AttributeError {
+            path: e.path().to_string(),
+            message: e.inner().to_string(),
+        }),
+        Ok(value) => Ok(value),
+    }
+}
+
+/// Maps `value` onto a DynamoDB item through its serde `Serialize` implementation.
+/// `None`s are left out of the item.
+pub fn to_attributes<T: Serialize>(
+    value: &T,
+) -> Result<HashMap<String, AttributeValue>, AttributeError> {
+    match serde_json::to_value(value) {
+        Err(e) => Err(AttributeError {
+            path: String::new(),
+            message: e.to_string(),
+        }),
+        Ok(Value::Object(object)) => Ok(json_to_map(object)),
+        Ok(_) => Err(AttributeError {
+            path: String::new(),
+            message: "only structs and maps can be stored as items".to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Nested {
+        #[serde(rename = "Count")]
+        count: u32,
+        #[serde(rename = "Tags")]
+        tags: Vec<String>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        #[serde(rename = "ID")]
+        id: u32,
+        #[serde(rename = "Ratio")]
+        ratio: f64,
+        #[serde(rename = "Enabled")]
+        enabled: bool,
+        #[serde(rename = "Note")]
+        note: Option<String>,
+        #[serde(rename = "Nested")]
+        nested: Nested,
+    }
+
+    fn record() -> Record {
+        Record {
+            id: 4551,
+            ratio: 0.5,
+            enabled: true,
+            note: None,
+            nested: Nested {
+                count: 3,
+                tags: vec!["a".to_string(), "b".to_string()],
+            },
+        }
+    }
+
+    #[test]
+    fn round_trips_through_attributes() {
+        let attributes = to_attributes(&record()).unwrap();
+        assert_eq!(attributes["ID"].n.as_deref(), Some("4551"));
+        assert_eq!(attributes["Enabled"].bool, Some(true));
+        assert!(!attributes.contains_key("Note"));
+        let nested = attributes["Nested"].m.as_ref().unwrap();
+        assert_eq!(nested["Tags"].l.as_ref().unwrap().len(), 2);
+        assert_eq!(from_attributes::<Record>(&attributes).unwrap(), record());
+    }
+
+    #[test]
+    fn reads_sets_as_lists() {
+        let mut attributes = to_attributes(&record()).unwrap();
+        let nested = attributes.get_mut("Nested").unwrap().m.as_mut().unwrap();
+        nested.insert(
+            "Tags".to_string(),
+            AttributeValue {
+                ss: Some(vec!["a".to_string(), "b".to_string()]),
+                ..Default::default()
+            },
+        );
+        assert_eq!(from_attributes::<Record>(&attributes).unwrap(), record());
+    }
+
+    #[test]
+    fn reports_the_path_of_bad_attributes() {
+        let mut attributes = to_attributes(&record()).unwrap();
+        let nested = attributes.get_mut("Nested").unwrap().m.as_mut().unwrap();
+        nested.insert(
+            "Count".to_string(),
+            AttributeValue {
+                s: Some("three".to_string()),
+                ..Default::default()
+            },
+        );
+        let error = from_attributes::<Record>(&attributes).unwrap_err();
+        assert_eq!(error.path, "Nested.Count");
+
+        attributes.insert(
+            "Ratio".to_string(),
+            AttributeValue {
+                n: Some("half".to_string()),
+                ..Default::default()
+            },
+        );
+        let error = from_attributes::<Record>(&attributes).unwrap_err();
+        assert_eq!(error.path, "Ratio");
+    }
+
+    #[test]
+    fn only_stores_structs_and_maps() {
+        assert!(to_attributes(&vec![1, 2]).is_err());
+    }
+}
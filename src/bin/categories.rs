@@ -0,0 +1,65 @@
+use env_logger;
+use lambda_http::{handler, lambda, Context, IntoResponse, Request};
+use log::{info, warn};
+
+use ffxiv_item_name_database_api::model::{
+    create_json_response, summarize_categories, CategorySummary, HttpErrorType,
+};
+use ffxiv_item_name_database_api::repository::load_items;
+use ffxiv_item_name_database_api::retry::invocation_deadline;
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::time::Instant;
+
+type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
+
+/// The summaries with the number of malformed items left out of them.
+static CATEGORIES: OnceCell<(Vec<CategorySummary>, usize)> = OnceCell::new();
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ResponseData<'a> {
+    results: &'a [CategorySummary],
+    warnings: usize,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    lambda::run(handler(lambda_handler)).await?;
+    Ok(())
+}
+
+async fn lambda_handler(event: Request, context: Context) -> Result<impl IntoResponse, Error> {
+    match env_logger::try_init() {
+        Err(e) => warn!("error occurred in env_logger::try_init(): {}", e),
+        Ok(_) => (),
+    };
+    info!("event: {:?}", event);
+    let (categories, warnings) = match load_categories(invocation_deadline(context.deadline)).await
+    {
+        Err(e) => return Ok(e.create_response()),
+        Ok(loaded) => loaded,
+    };
+    let body = ResponseData {
+        results: categories,
+        warnings: *warnings,
+    };
+    Ok(create_json_response(
+        &body,
+        "the categories are too many to return.",
+    ))
+}
+
+/// Summarizes the categories on the first call in this container and reuses them afterwards.
+async fn load_categories(
+    deadline: Instant,
+) -> Result<&'static (Vec<CategorySummary>, usize), HttpErrorType> {
+    if let Some(loaded) = CATEGORIES.get() {
+        return Ok(loaded);
+    }
+    let (items, warnings) = match load_items(deadline).await {
+        Err(e) => return Err(e),
+        Ok(loaded) => loaded,
+    };
+    Ok(CATEGORIES.get_or_init(|| (summarize_categories(&items), warnings)))
+}
@@ -0,0 +1,114 @@
+use env_logger;
+use lambda_http::{handler, lambda, Context, IntoResponse, Request, RequestExt, Response};
+use log::{info, warn};
+
+use ffxiv_item_name_database_api::model::{create_json_response, HttpErrorType};
+use ffxiv_item_name_database_api::projection::Projection;
+use ffxiv_item_name_database_api::repository::{DynamoDbItemRepository, ItemRepository};
+use ffxiv_item_name_database_api::retry::invocation_deadline;
+
+#[cfg(test)]
+#[path = "../../tests/common/mod.rs"]
+mod common;
+
+type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    lambda::run(handler(lambda_handler)).await?;
+    Ok(())
+}
+
+async fn lambda_handler(event: Request, context: Context) -> Result<impl IntoResponse, Error> {
+    match env_logger::try_init() {
+        Err(e) => warn!("error occurred in env_logger::try_init(): {}", e),
+        Ok(_) => (),
+    };
+    info!("event: {:?}", event);
+    let path_parameters = event.path_parameters();
+    let repository = match DynamoDbItemRepository::from_env() {
+        Err(e) => return Ok(e.create_response()),
+        Ok(repository) => repository.with_deadline(invocation_deadline(context.deadline)),
+    };
+    Ok(get_item(path_parameters.get("id"), &repository).await)
+}
+
+async fn get_item(id: Option<&str>, repository: &dyn ItemRepository) -> Response<String> {
+    let id = match parse_id(id) {
+        Err(e) => return e.create_response(),
+        Ok(id) => id,
+    };
+
+    let item = match repository.get_one(id).await {
+        Err(e) => return e.create_response(),
+        Ok(None) => {
+            return HttpErrorType::NotFound(format!("item {} does not exist.", id))
+                .create_response()
+        }
+        Ok(Some(item)) => item,
+    };
+
+    create_json_response(
+        &Projection::default().apply(&item),
+        "the item is too large to return.",
+    )
+}
+
+fn parse_id(id: Option<&str>) -> Result<u32, HttpErrorType> {
+    match id {
+        None => Err(HttpErrorType::BadRequest("id is required.".to_string())),
+        Some(text) => match text.parse::<u32>() {
+            Err(_) => Err(HttpErrorType::BadRequest(format!(
+                "id '{}' must be a number.",
+                text
+            ))),
+            Ok(id) => Ok(id),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{parse, repository};
+    use serde_json::Value;
+
+    async fn get(id: Option<&str>) -> (u16, Value) {
+        parse(get_item(id, &repository()).await)
+    }
+
+    #[tokio::test]
+    async fn returns_the_item() {
+        let (status, body) = get(Some("4551")).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["Name_en"], "Potion");
+        assert_eq!(
+            body["AvailableLanguages"],
+            serde_json::json!(["de", "fr", "en", "ja", "zh", "ko"])
+        );
+    }
+
+    #[tokio::test]
+    async fn leaves_out_missing_translations() {
+        let (status, body) = get(Some("12345")).await;
+        assert_eq!(status, 200);
+        assert_eq!(body.get("Name_de"), None);
+        assert_eq!(body.get("EorzeaDatabaseId"), None);
+        assert_eq!(body["AvailableLanguages"], serde_json::json!(["en", "ja"]));
+    }
+
+    #[tokio::test]
+    async fn returns_not_found_for_unknown_ids() {
+        let (status, body) = get(Some("99999")).await;
+        assert_eq!(status, 404);
+        assert_eq!(body["type"], "NotFound");
+    }
+
+    #[tokio::test]
+    async fn rejects_ids_that_are_not_numbers() {
+        let (status, _) = get(Some("potion")).await;
+        assert_eq!(status, 400);
+        let (status, _) = get(None).await;
+        assert_eq!(status, 400);
+    }
+}
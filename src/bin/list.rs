@@ -1,18 +1,29 @@
 use env_logger;
+use ffxiv_item_name_database_api::filter::CategoryFilter;
 use ffxiv_item_name_database_api::model::{
-    convert_dynamodb_item_to_item, get_table_name, parse_query, sort_func, HttpErrorType, Item,
+    create_json_response, parse_query, sort_func, HttpErrorType, Item,
 };
-use lambda_http::{handler, lambda, Context, IntoResponse, Request, Response};
+use ffxiv_item_name_database_api::projection::Projection;
+use ffxiv_item_name_database_api::repository::{DynamoDbItemRepository, ItemRepository};
+use ffxiv_item_name_database_api::retry::invocation_deadline;
+use lambda_http::http::Method;
+use lambda_http::{handler, lambda, Body, Context, IntoResponse, Request, Response};
 use log::{info, warn};
-use rusoto_core::Region;
-use rusoto_dynamodb::{
-    AttributeValue, BatchGetItemInput, DynamoDb, DynamoDbClient, KeysAndAttributes,
-};
-use serde::Serialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::str::FromStr;
+
+#[cfg(test)]
+#[path = "../../tests/common/mod.rs"]
+mod common;
 
 type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
 
+/// Used when `LIST_MAX_IDS` is not set.
+const DEFAULT_MAX_IDS: usize = 1000;
+
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     lambda::run(handler(lambda_handler)).await?;
@@ -22,140 +33,555 @@ async fn main() -> Result<(), Error> {
 #[derive(Debug, Serialize)]
 struct Condition {
     ids: Vec<u32>,
+    #[serde(flatten)]
+    category_filter: CategoryFilter,
+    strict: bool,
+    order: String,
+    format: String,
+    #[serde(flatten)]
+    projection: Projection,
+}
+
+/// JSON form of a `POST /api/list` body.
+#[derive(Debug, Deserialize)]
+struct RequestBody {
+    ids: Vec<u32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum Results {
+    List(Vec<Map<String, Value>>),
+    Map(BTreeMap<u32, Map<String, Value>>),
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct ResponseData {
     condition: Condition,
-    results: Vec<Item>,
+    results: Results,
+    missing: Vec<u32>,
+    /// IDs whose items exist but are malformed. They are counted in `warnings` too.
+    skipped: Vec<u32>,
+    warnings: usize,
+}
+
+/// Order of `Results` when they are returned as a list.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ListOrder {
+    /// The order the IDs were requested in.
+    Request,
+    /// `sort_func` order, by `ItemSearchCategory` and then by ID.
+    Category,
+    Id,
+}
+
+impl FromStr for ListOrder {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "request" => Ok(ListOrder::Request),
+            "category" => Ok(ListOrder::Category),
+            "id" => Ok(ListOrder::Id),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ListOrder {
+    fn to_string(&self) -> String {
+        match self {
+            ListOrder::Request => "request",
+            ListOrder::Category => "category",
+            ListOrder::Id => "id",
+        }
+        .to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ResultFormat {
+    List,
+    /// An object keyed by item ID.
+    Map,
+}
+
+impl FromStr for ResultFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "list" => Ok(ResultFormat::List),
+            "map" => Ok(ResultFormat::Map),
+            _ => Err(()),
+        }
+    }
 }
 
-async fn lambda_handler(event: Request, _: Context) -> Result<impl IntoResponse, Error> {
+impl ResultFormat {
+    fn to_string(&self) -> String {
+        match self {
+            ResultFormat::List => "list",
+            ResultFormat::Map => "map",
+        }
+        .to_string()
+    }
+}
+
+async fn lambda_handler(event: Request, context: Context) -> Result<impl IntoResponse, Error> {
     match env_logger::try_init() {
         Err(e) => warn!("error occurred in env_logger::try_init(): {}", e),
         Ok(_) => (),
     };
     info!("event: {:?}", event);
     let query = parse_query(&event);
-    let ids = match parse_ids(&query) {
+    let body: Option<String> = if event.method() == Method::POST {
+        match event.body() {
+            Body::Empty => None,
+            Body::Text(text) => Some(text.clone()),
+            Body::Binary(bytes) => match String::from_utf8(bytes.clone()) {
+                Err(_) => {
+                    return Ok(
+                        HttpErrorType::BadRequest("body must be UTF-8 text.".to_string())
+                            .create_response(),
+                    )
+                }
+                Ok(text) => Some(text),
+            },
+        }
+    } else {
+        None
+    };
+    let repository = match DynamoDbItemRepository::from_env() {
         Err(e) => return Ok(e.create_response()),
+        Ok(repository) => repository.with_deadline(invocation_deadline(context.deadline)),
+    };
+    info!("table name: {}", repository.table_name());
+    Ok(list(&query, body.as_deref(), &repository).await)
+}
+
+/// Lists the items named by `ids`, read from `body` for POST requests and from the query
+/// string otherwise. The other parameters always come from the query string.
+async fn list(
+    query: &HashMap<String, String>,
+    body: Option<&str>,
+    repository: &dyn ItemRepository,
+) -> Response<String> {
+    let max_ids = match get_max_ids() {
+        Err(e) => return e.create_response(),
+        Ok(max_ids) => max_ids,
+    };
+
+    let ids = match body {
+        None => parse_ids(query, max_ids),
+        Some(body) => parse_body_ids(body, max_ids),
+    };
+    let ids = match ids {
+        Err(e) => return e.create_response(),
         Ok(ids) => ids,
     };
 
-    let table_name = match get_table_name() {
-        Err(e) => return Ok(e.create_response()),
-        Ok(name) => name,
+    let category_filter = match CategoryFilter::parse(query) {
+        Err(e) => return e.create_response(),
+        Ok(filter) => filter,
     };
-    info!("table name: {}", table_name);
-    let filtered = match get_data(&ids, &table_name).await {
-        Err(e) => return Ok(e.create_response()),
-        Ok(data) => data,
+
+    let strict = match parse_strict(query) {
+        Err(e) => return e.create_response(),
+        Ok(strict) => strict,
+    };
+
+    let order: ListOrder = match query.get("order") {
+        None => ListOrder::Category,
+        Some(order) => match ListOrder::from_str(order) {
+            Err(_) => {
+                return HttpErrorType::BadRequest(format!("order '{}' is invalid.", order))
+                    .create_response()
+            }
+            Ok(order) => order,
+        },
+    };
+
+    let format: ResultFormat = match query.get("format") {
+        None => ResultFormat::List,
+        Some(format) => match ResultFormat::from_str(format) {
+            Err(_) => {
+                return HttpErrorType::BadRequest(format!("format '{}' is invalid.", format))
+                    .create_response()
+            }
+            Ok(format) => format,
+        },
+    };
+
+    let projection = match Projection::parse(query) {
+        Err(e) => return e.create_response(),
+        Ok(projection) => projection,
+    };
+
+    let (filtered, missing, skipped) =
+        match get_data(&ids, &category_filter, &order, &projection, repository).await {
+            Err(e) => return e.create_response(),
+            Ok(data) => data,
+        };
+
+    if strict && !missing.is_empty() {
+        let missing: Vec<String> = missing.iter().map(|id| id.to_string()).collect();
+        return HttpErrorType::NotFound(format!("ids {} do not exist.", missing.join(",")))
+            .create_response();
+    }
+
+    let results = match format {
+        ResultFormat::List => {
+            Results::List(filtered.iter().map(|item| projection.apply(item)).collect())
+        }
+        ResultFormat::Map => Results::Map(
+            filtered
+                .iter()
+                .map(|item| (item.id, projection.apply(item)))
+                .collect(),
+        ),
     };
 
     let body = ResponseData {
-        condition: Condition { ids: ids },
-        results: filtered,
+        condition: Condition {
+            ids,
+            category_filter,
+            strict,
+            order: order.to_string(),
+            format: format.to_string(),
+            projection,
+        },
+        results,
+        missing,
+        skipped,
+        warnings: repository.warnings(),
     };
 
-    Ok(Response::builder()
-        .status(200)
-        .header("Access-Control-Allow-Origin", "*")
-        .header("Content-Type", "application/json")
-        .body(serde_json::to_string(&body).unwrap())
-        .expect("failed"))
+    create_json_response(&body, "ask for fewer ids or fewer fields.")
 }
 
-fn parse_ids(query: &HashMap<String, String>) -> Result<Vec<u32>, HttpErrorType> {
-    let mut result: Vec<u32> = Vec::new();
+fn get_max_ids() -> Result<usize, HttpErrorType> {
+    match env::var("LIST_MAX_IDS") {
+        Err(_) => Ok(DEFAULT_MAX_IDS),
+        Ok(text) => match text.parse::<usize>() {
+            Ok(max_ids) if max_ids > 0 => Ok(max_ids),
+            _ => Err(HttpErrorType::InternalServerError(format!(
+                "Environment Value LIST_MAX_IDS '{}' is invalid.",
+                text
+            ))),
+        },
+    }
+}
+
+fn parse_ids(query: &HashMap<String, String>, max_ids: usize) -> Result<Vec<u32>, HttpErrorType> {
+    match query.get("ids") {
+        None => Err(HttpErrorType::BadRequest("ids is required.".to_string())),
+        Some(text) => parse_id_list(text, max_ids),
+    }
+}
+
+/// Reads IDs from a POST body, either `{"ids": [...]}` or the same comma separated
+/// text the query string takes.
+fn parse_body_ids(body: &str, max_ids: usize) -> Result<Vec<u32>, HttpErrorType> {
+    let body = body.trim();
+    if !body.starts_with('{') {
+        return parse_id_list(body, max_ids);
+    }
+    match serde_json::from_str::<RequestBody>(body) {
+        Err(e) => Err(HttpErrorType::BadRequest(format!("body is invalid: {}", e))),
+        Ok(request) => check_ids(request.ids, max_ids),
+    }
+}
 
-    let text: &String = match query.get("ids") {
-        None => return Err(HttpErrorType::BadRequest("ids is required.".to_string())),
-        Some(text) => text,
+/// Reads comma separated IDs and ID ranges such as `1-50,100,200-210`. Line breaks are
+/// accepted as separators too, so a CSV column can be posted as it is.
+///
+/// Ranges are expanded here, so every distinct ID they cover counts towards `max_ids`.
+fn parse_id_list(text: &str, max_ids: usize) -> Result<Vec<u32>, HttpErrorType> {
+    let too_many = || {
+        HttpErrorType::BadRequest(format!(
+            "too many ids: ids expand to more than {} ids.",
+            max_ids
+        ))
     };
+    let mut result: Vec<u32> = Vec::new();
+    let mut seen: HashSet<u32> = HashSet::new();
 
-    for raw in text.split(",") {
-        let num = match raw.parse::<u32>() {
-            Err(_) => {
-                return Err(HttpErrorType::BadRequest(
-                    "ids must be comma separated numbers".to_string(),
-                ))
-            }
-            Ok(num) => num,
+    for raw in text.split(|c| c == ',' || c == '\n') {
+        let raw = raw.trim();
+        let bounds: Vec<&str> = raw.splitn(2, '-').map(|bound| bound.trim()).collect();
+        let (start, end) = match (bounds[0].parse::<u32>(), bounds.get(1)) {
+            (Ok(num), None) => (num, num),
+            (Ok(start), Some(end)) => match end.parse::<u32>() {
+                Err(_) => return Err(invalid_ids()),
+                Ok(end) => (start, end),
+            },
+            (Err(_), _) => return Err(invalid_ids()),
         };
-        result.push(num);
+        if start > end {
+            return Err(HttpErrorType::BadRequest(format!(
+                "range '{}' is reversed.",
+                raw
+            )));
+        }
+        // a range this wide holds more distinct IDs than allowed, whatever else was given
+        if (end - start) as u64 >= max_ids as u64 {
+            return Err(too_many());
+        }
+
+        for id in start..=end {
+            if seen.insert(id) {
+                result.push(id);
+            }
+        }
+        if result.len() > max_ids {
+            return Err(too_many());
+        }
+    }
+
+    check_ids(result, max_ids)
+}
+
+fn invalid_ids() -> HttpErrorType {
+    HttpErrorType::BadRequest("ids must be comma separated numbers or ranges like 1-50".to_string())
+}
+
+/// Drops repeated IDs, keeping the request order, and enforces `max_ids`.
+fn check_ids(ids: Vec<u32>, max_ids: usize) -> Result<Vec<u32>, HttpErrorType> {
+    let mut seen: HashSet<u32> = HashSet::new();
+    let result: Vec<u32> = ids.into_iter().filter(|id| seen.insert(*id)).collect();
+
+    if result.is_empty() {
+        return Err(HttpErrorType::BadRequest("ids is required.".to_string()));
+    }
+    if result.len() > max_ids {
+        return Err(HttpErrorType::BadRequest(format!(
+            "too many ids: {} were given but at most {} are allowed.",
+            result.len(),
+            max_ids
+        )));
     }
 
     Ok(result)
 }
 
-async fn get_data(ids: &Vec<u32>, table_name: &String) -> Result<Vec<Item>, HttpErrorType> {
-    let mut result: Vec<Item> = Vec::new();
-    let client = DynamoDbClient::new(Region::default());
+fn parse_strict(query: &HashMap<String, String>) -> Result<bool, HttpErrorType> {
+    match query.get("strict").map(|text| text.as_str()) {
+        None | Some("false") => Ok(false),
+        Some("true") => Ok(true),
+        Some(text) => Err(HttpErrorType::BadRequest(format!(
+            "strict '{}' is invalid.",
+            text
+        ))),
+    }
+}
+
+/// Fetches the items of `ids` and reports, in request order, the IDs that have no item
+/// and the IDs whose items were skipped as malformed.
+async fn get_data(
+    ids: &[u32],
+    category_filter: &CategoryFilter,
+    order: &ListOrder,
+    projection: &Projection,
+    repository: &dyn ItemRepository,
+) -> Result<(Vec<Item>, Vec<u32>, Vec<u32>), HttpErrorType> {
+    let mut result = match repository.batch_get_projected(ids, projection).await {
+        Err(e) => return Err(e),
+        Ok(items) => items,
+    };
+
+    let found: HashSet<u32> = result.iter().map(|item| item.id).collect();
+    let skipped_ids: HashSet<u32> = repository.skipped_ids().into_iter().collect();
+    let (skipped, missing): (Vec<u32>, Vec<u32>) = ids
+        .iter()
+        .filter(|id| !found.contains(id))
+        .partition(|id| skipped_ids.contains(id));
+
+    result.retain(|item| category_filter.matches(item));
 
-    for chunk in ids.chunks(100) {
-        let mut keys_and_attributes: Option<KeysAndAttributes> = Some(KeysAndAttributes {
-            keys: chunk
+    match order {
+        ListOrder::Request => {
+            let positions: HashMap<u32, usize> = ids
                 .iter()
-                .map(|id| {
-                    let mut map: HashMap<String, AttributeValue> = HashMap::new();
-                    let attr = AttributeValue {
-                        n: Some(id.to_string()),
-                        ..Default::default()
-                    };
+                .enumerate()
+                .map(|(position, id)| (*id, position))
+                .collect();
+            result.sort_by_key(|item| positions.get(&item.id).cloned());
+        }
+        ListOrder::Category => result.sort_by(sort_func),
+        ListOrder::Id => result.sort_by_key(|item| item.id),
+    }
 
-                    map.insert("ID".to_string(), attr);
+    Ok((result, missing, skipped))
+}
 
-                    map
-                })
-                .collect(),
-            ..Default::default()
-        });
-        while keys_and_attributes.is_some() {
-            let current: KeysAndAttributes = keys_and_attributes.unwrap();
-
-            let mut request_items: HashMap<String, KeysAndAttributes> = HashMap::new();
-            request_items.insert(table_name.clone(), current);
-            let input = BatchGetItemInput {
-                request_items: request_items,
-                ..Default::default()
-            };
-
-            let resp = match client.batch_get_item(input).await {
-                Err(e) => {
-                    return Err(HttpErrorType::InternalServerError(format!(
-                        "failed fetch data: {}",
-                        e
-                    )))
-                }
-                Ok(resp) => resp,
-            };
-            match resp.responses {
-                None => (),
-                Some(table_response) => match table_response.get(table_name) {
-                    None => (),
-                    Some(items) => {
-                        for item in items {
-                            match convert_dynamodb_item_to_item(item) {
-                                Err(e) => return Err(e),
-                                Ok(item) => result.push(item),
-                            }
-                        }
-                    }
-                },
-            };
-
-            keys_and_attributes = match resp.unprocessed_keys {
-                None => None,
-                Some(unprocessed) => match unprocessed.get(table_name) {
-                    None => None,
-                    Some(keys) => Some(keys.clone()),
-                },
-            };
-        }
-    }
-
-    result.sort_by(sort_func);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{ids, parse, query, repository};
+    use async_trait::async_trait;
+    use ffxiv_item_name_database_api::model::{Language, MatchMode};
+    use ffxiv_item_name_database_api::repository::{InMemoryItemRepository, NameMatch};
 
-    Ok(result)
+    async fn get(pairs: &[(&str, &str)], body: Option<&str>) -> (u16, Value) {
+        parse(list(&query(pairs), body, &repository()).await)
+    }
+
+    #[tokio::test]
+    async fn lists_items_and_reports_missing_ids() {
+        let (status, body) = get(&[("ids", "4551,1,99999")], None).await;
+        assert_eq!(status, 200);
+        assert_eq!(ids(&body), vec![1, 4551]);
+        assert_eq!(body["Missing"], serde_json::json!([99999]));
+    }
+
+    /// Pretends the items of `skipped` are stored but malformed.
+    struct SkippingRepository {
+        inner: InMemoryItemRepository,
+        skipped: Vec<u32>,
+    }
+
+    #[async_trait]
+    impl ItemRepository for SkippingRepository {
+        async fn search_by_name(
+            &self,
+            langs: &[Language],
+            string: &str,
+            mode: &MatchMode,
+        ) -> Result<Vec<NameMatch>, HttpErrorType> {
+            self.inner.search_by_name(langs, string, mode).await
+        }
+
+        async fn batch_get_by_ids(&self, ids: &[u32]) -> Result<Vec<Item>, HttpErrorType> {
+            let ids: Vec<u32> = ids
+                .iter()
+                .filter(|id| !self.skipped.contains(id))
+                .cloned()
+                .collect();
+            self.inner.batch_get_by_ids(&ids).await
+        }
+
+        async fn scan_all(&self) -> Result<Vec<Item>, HttpErrorType> {
+            self.inner.scan_all().await
+        }
+
+        async fn get_one(&self, id: u32) -> Result<Option<Item>, HttpErrorType> {
+            self.inner.get_one(id).await
+        }
+
+        fn warnings(&self) -> usize {
+            self.skipped.len()
+        }
+
+        fn skipped_ids(&self) -> Vec<u32> {
+            self.skipped.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_skipped_ids_out_of_missing() {
+        let repository = SkippingRepository {
+            inner: repository(),
+            skipped: vec![4552],
+        };
+        let (status, body) = parse(
+            list(
+                &query(&[("ids", "4551,4552,99999"), ("strict", "false")]),
+                None,
+                &repository,
+            )
+            .await,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(ids(&body), vec![4551]);
+        assert_eq!(body["Missing"], serde_json::json!([99999]));
+        assert_eq!(body["Skipped"], serde_json::json!([4552]));
+        assert_eq!(body["Warnings"], 1);
+
+        let response = list(
+            &query(&[("ids", "4551,4552"), ("strict", "true")]),
+            None,
+            &repository,
+        )
+        .await;
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn filters_by_category() {
+        let (status, body) = get(&[("ids", "1,4551,1601"), ("category", "43")], None).await;
+        assert_eq!(status, 200);
+        assert_eq!(ids(&body), vec![4551]);
+        assert_eq!(body["Missing"], serde_json::json!([]));
+
+        let (status, body) = get(&[("ids", "1,4551"), ("marketable", "true")], None).await;
+        assert_eq!(status, 200);
+        assert_eq!(ids(&body), vec![4551]);
+
+        let (status, _) = get(&[("ids", "1,4551"), ("category", "abc")], None).await;
+        assert_eq!(status, 400);
+        let (status, _) = get(&[("ids", "1,4551"), ("marketable", "maybe")], None).await;
+        assert_eq!(status, 400);
+    }
+
+    #[tokio::test]
+    async fn strict_rejects_missing_ids() {
+        let (status, body) = get(&[("ids", "4551,99999"), ("strict", "true")], None).await;
+        assert_eq!(status, 404);
+        assert_eq!(body["type"], "NotFound");
+    }
+
+    #[tokio::test]
+    async fn reads_ids_from_post_body() {
+        let (status, body) = get(&[("order", "request")], Some(r#"{"ids": [4552, 4551]}"#)).await;
+        assert_eq!(status, 200);
+        assert_eq!(ids(&body), vec![4552, 4551]);
+
+        let (status, body) = get(&[("order", "id")], Some("4552\n4551\n")).await;
+        assert_eq!(status, 200);
+        assert_eq!(ids(&body), vec![4551, 4552]);
+    }
+
+    #[tokio::test]
+    async fn keys_results_by_id_in_map_format() {
+        let (status, body) = get(&[("ids", "4551,4555"), ("format", "map")], None).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["Results"]["4555"]["Name_fr"], "Éther");
+        assert_eq!(body["Results"].as_object().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn expands_id_ranges() {
+        assert_eq!(parse_id_list("1-3,7", 10).unwrap(), vec![1, 2, 3, 7]);
+        assert_eq!(parse_id_list(" 1 - 3 \n2", 10).unwrap(), vec![1, 2, 3]);
+        assert_eq!(parse_id_list("5,1-3,2", 10).unwrap(), vec![5, 1, 2, 3]);
+        assert_eq!(parse_id_list("4-4", 10).unwrap(), vec![4]);
+    }
+
+    #[test]
+    fn counts_distinct_ids_against_the_limit() {
+        assert_eq!(parse_id_list("1-600,1-600", 1000).unwrap().len(), 600);
+        assert_eq!(parse_id_list("1-10,5-15", 15).unwrap().len(), 15);
+        assert!(parse_id_list("1-10,5-16", 15).is_err());
+        assert!(parse_id_list("1-4294967295", 1000).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_ranges() {
+        assert!(parse_id_list("3-1", 10).is_err());
+        assert!(parse_id_list("1-", 10).is_err());
+        assert!(parse_id_list("-1", 10).is_err());
+        assert!(parse_id_list("1-2-3", 10).is_err());
+        assert!(parse_id_list("a-b", 10).is_err());
+        assert!(parse_id_list("", 10).is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_ids() {
+        let (status, _) = get(&[("ids", "4551,abc")], None).await;
+        assert_eq!(status, 400);
+        let (status, _) = get(&[], None).await;
+        assert_eq!(status, 400);
+    }
 }
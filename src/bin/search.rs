@@ -3,29 +3,76 @@ use lambda_http::{handler, lambda, Context, IntoResponse, Request, Response};
 use log::{info, warn};
 use std::collections::HashMap;
 
+use ffxiv_item_name_database_api::filter::CategoryFilter;
 use ffxiv_item_name_database_api::model::{
-    convert_dynamodb_item_to_item, get_table_name, parse_query, sort_func, HttpErrorType, Item,
-    Language,
+    create_json_response, get_accept_language, parse_query, resolve_languages,
+    vary_on_accept_language, HttpErrorType, Language, MatchMode, SearchMode,
 };
-use maplit::hashmap;
-use rusoto_core::Region;
-use rusoto_dynamodb::{AttributeValue, DynamoDb, DynamoDbClient, ScanInput};
+use ffxiv_item_name_database_api::pagination::{Cursor, Page};
+use ffxiv_item_name_database_api::projection::Projection;
+use ffxiv_item_name_database_api::repository::{
+    load_in_memory_repository, ItemRepository, NameMatch,
+};
+use ffxiv_item_name_database_api::retry::invocation_deadline;
 use serde::Serialize;
+use serde_json::{Map, Value};
 use std::str::FromStr;
 
+#[cfg(test)]
+#[path = "../../tests/common/mod.rs"]
+mod common;
+
 type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
 
 #[derive(Debug, Serialize)]
 struct Condition {
     string: String,
     language: String,
+    #[serde(rename = "match")]
+    match_mode: String,
+    mode: String,
+    #[serde(flatten)]
+    category_filter: CategoryFilter,
+    limit: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<String>,
+    #[serde(flatten)]
+    projection: Projection,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SearchResult {
+    #[serde(flatten)]
+    item: Map<String, Value>,
+    matched_languages: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<f64>,
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct ResponseData {
     condition: Condition,
-    results: Vec<Item>,
+    results: Vec<SearchResult>,
+    next_cursor: Option<String>,
+    warnings: usize,
+}
+
+struct SearchCondition {
+    langs: Vec<Language>,
+    string: String,
+    match_mode: MatchMode,
+    search_mode: SearchMode,
+}
+
+/// Everything `search` reads from the query string, parsed before any item is loaded.
+struct SearchRequest {
+    condition: SearchCondition,
+    category_filter: CategoryFilter,
+    page: Page,
+    projection: Projection,
+    cursor: Option<String>,
 }
 
 #[tokio::main]
@@ -34,7 +81,7 @@ async fn main() -> Result<(), Error> {
     Ok(())
 }
 
-async fn lambda_handler(event: Request, _: Context) -> Result<impl IntoResponse, Error> {
+async fn lambda_handler(event: Request, context: Context) -> Result<impl IntoResponse, Error> {
     match env_logger::try_init() {
         Err(e) => warn!("error occurred in env_logger::try_init(): {}", e),
         Ok(_) => (),
@@ -42,112 +89,334 @@ async fn lambda_handler(event: Request, _: Context) -> Result<impl IntoResponse,
     info!("event: {:?}", event);
     let query = parse_query(&event);
     info!("query: {:?}", query);
-    let (lang, string) = match parse_condition(&query) {
-        Err(e) => return Ok(e.create_response()),
-        Ok(result) => result,
+    let accept_language = get_accept_language(&event);
+    // A cold container scans the whole table, so bad queries are answered before that.
+    let request = match parse_request(&query, accept_language.as_deref()) {
+        Err(e) => return Ok(vary_on_accept_language(&query, e.create_response())),
+        Ok(request) => request,
     };
-    let table_name = match get_table_name() {
+    let repository = match load_in_memory_repository(invocation_deadline(context.deadline)).await {
         Err(e) => return Ok(e.create_response()),
-        Ok(name) => name,
+        Ok(repository) => repository,
     };
-    let items = match scan_and_sort(&lang, &string, &table_name).await {
-        Err(e) => return Ok(e.create_response()),
-        Ok(items) => items,
+    let response = search(request, repository).await;
+    Ok(vary_on_accept_language(&query, response))
+}
+
+fn parse_request(
+    query: &HashMap<String, String>,
+    accept_language: Option<&str>,
+) -> Result<SearchRequest, HttpErrorType> {
+    let condition = match parse_condition(query, accept_language) {
+        Err(e) => return Err(e),
+        Ok(condition) => condition,
     };
+    let category_filter = match CategoryFilter::parse(query) {
+        Err(e) => return Err(e),
+        Ok(filter) => filter,
+    };
+    let page = match Page::parse(query) {
+        Err(e) => return Err(e),
+        Ok(page) => page,
+    };
+    let projection = match Projection::parse(query) {
+        Err(e) => return Err(e),
+        Ok(projection) => projection,
+    };
+    Ok(SearchRequest {
+        condition,
+        category_filter,
+        page,
+        projection,
+        cursor: query.get("cursor").cloned(),
+    })
+}
+
+async fn search(request: SearchRequest, repository: &dyn ItemRepository) -> Response<String> {
+    let SearchRequest {
+        condition,
+        category_filter,
+        page,
+        projection,
+        cursor,
+    } = request;
+    let mut matches = match search_and_sort(&condition, repository).await {
+        Err(e) => return e.create_response(),
+        Ok(matches) => matches,
+    };
+    matches.retain(|m| category_filter.matches(&m.item));
+    let (matches, next_cursor) = page.apply(matches, |m| Cursor::of(&m.item, m.score));
+    let results = matches
+        .into_iter()
+        .map(|m| SearchResult {
+            item: projection.apply(&m.item),
+            matched_languages: m.languages.iter().map(|lang| lang.to_string()).collect(),
+            score: m.score,
+        })
+        .collect();
     let body = ResponseData {
         condition: Condition {
-            language: lang.to_string(),
-            string: string.clone(),
+            language: condition
+                .langs
+                .iter()
+                .map(|lang| lang.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+            string: condition.string.clone(),
+            match_mode: condition.match_mode.to_string(),
+            mode: condition.search_mode.to_string(),
+            category_filter,
+            limit: page.limit,
+            cursor,
+            projection,
         },
-        results: items,
+        results,
+        next_cursor,
+        warnings: repository.warnings(),
     };
-    Ok(Response::builder()
-        .status(200)
-        .header("Access-Control-Allow-Origin", "*")
-        .header("Content-Type", "application/json")
-        .body(serde_json::to_string(&body).unwrap())
-        .expect("failed"))
+    create_json_response(&body, "ask for a lower limit or fewer fields.")
 }
 
-fn parse_condition(query: &HashMap<String, String>) -> Result<(Language, String), HttpErrorType> {
-    let lang: Language = match query.get("language") {
-        None => {
-            return Err(HttpErrorType::BadRequest(
-                "language is required.".to_string(),
-            ))
-        }
-        Some(lang) => match Language::from_str(lang) {
+/// Without `language` the query searches the language `accept_language` prefers.
+fn parse_condition(
+    query: &HashMap<String, String>,
+    accept_language: Option<&str>,
+) -> Result<SearchCondition, HttpErrorType> {
+    let langs: Vec<Language> = match resolve_languages(query, accept_language) {
+        Err(e) => return Err(e),
+        Ok(langs) => langs,
+    };
+    let string: String = match query.get("string") {
+        None => return Err(HttpErrorType::BadRequest("string is required.".to_string())),
+        Some(string) => string.clone(),
+    };
+    let search_mode: SearchMode = match query.get("mode") {
+        None => SearchMode::Substring,
+        Some(mode) => match SearchMode::from_str(mode) {
             Err(_) => {
                 return Err(HttpErrorType::BadRequest(format!(
-                    "language '{}' is invalid.",
-                    lang
+                    "mode '{}' is invalid.",
+                    mode
                 )))
             }
-            Ok(lang) => lang,
+            Ok(mode) => mode,
         },
     };
-    let string: String = match query.get("string") {
-        None => return Err(HttpErrorType::BadRequest("string is required.".to_string())),
-        Some(string) => string.clone(),
+    // Fuzzy scores are meant to forgive typing differences, so they compare
+    // normalized names unless asked otherwise.
+    let match_mode: MatchMode = match query.get("match") {
+        None => match search_mode {
+            SearchMode::Substring => MatchMode::Exact,
+            SearchMode::Fuzzy => MatchMode::Normalized,
+        },
+        Some(mode) => match MatchMode::from_str(mode) {
+            Err(_) => {
+                return Err(HttpErrorType::BadRequest(format!(
+                    "match '{}' is invalid.",
+                    mode
+                )))
+            }
+            Ok(mode) => mode,
+        },
     };
-    Ok((lang, string))
+    // Every name contains the empty string, so it would page through the whole table.
+    if langs
+        .iter()
+        .any(|lang| match_mode.apply(lang, &string).is_empty())
+    {
+        return Err(HttpErrorType::BadRequest(format!(
+            "string '{}' has nothing to search for.",
+            string
+        )));
+    }
+    Ok(SearchCondition {
+        langs,
+        string,
+        match_mode,
+        search_mode,
+    })
 }
 
-async fn scan_and_sort(
-    lang: &Language,
-    string: &String,
-    table_name: &String,
-) -> Result<Vec<Item>, HttpErrorType> {
-    let mut result: Vec<Item> = Vec::new();
-
-    let client = DynamoDbClient::new(Region::default());
-    let mut last_evaluated_key: Option<HashMap<String, AttributeValue>> = None;
-
-    while {
-        let input = ScanInput {
-            table_name: table_name.clone(),
-            filter_expression: Some("contains(#path, :value)".to_string()),
-            expression_attribute_names: Some(hashmap! {
-                "#path".to_string() => lang.get_key()
-            }),
-            expression_attribute_values: Some(hashmap! {
-                ":value".to_string() => AttributeValue {
-                    s: Some(string.clone()),
-                    ..Default::default()
-                }
-            }),
-            exclusive_start_key: last_evaluated_key.clone(),
-            ..Default::default()
-        };
-
-        let resp = match client.scan(input).await {
-            Err(e) => {
-                return Err(HttpErrorType::InternalServerError(format!(
-                    "error occurred in scan: {}",
-                    e
-                )))
-            }
-            Ok(resp) => resp,
-        };
+async fn search_and_sort(
+    condition: &SearchCondition,
+    repository: &dyn ItemRepository,
+) -> Result<Vec<NameMatch>, HttpErrorType> {
+    let SearchCondition {
+        langs,
+        string,
+        match_mode,
+        search_mode,
+    } = condition;
+    let found = match search_mode {
+        SearchMode::Substring => repository.search_by_name(langs, string, match_mode).await,
+        SearchMode::Fuzzy => {
+            repository
+                .fuzzy_search_by_name(langs, string, match_mode)
+                .await
+        }
+    };
+    let mut result = match found {
+        Err(e) => return Err(e),
+        Ok(matches) => matches,
+    };
 
-        last_evaluated_key = resp.last_evaluated_key;
+    result.sort_by(|a, b| Cursor::of(&a.item, a.score).compare(&Cursor::of(&b.item, b.score)));
 
-        let items: Vec<HashMap<String, AttributeValue>> = match resp.items {
-            None => Vec::new(),
-            Some(items) => items,
-        };
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{ids, parse, query, repository};
 
-        for item in items {
-            result.push(match convert_dynamodb_item_to_item(&item) {
-                Err(e) => return Err(e),
-                Ok(item) => item,
-            });
+    async fn respond(
+        query: &HashMap<String, String>,
+        accept_language: Option<&str>,
+    ) -> (u16, Value) {
+        match parse_request(query, accept_language) {
+            Err(e) => parse(e.create_response()),
+            Ok(request) => parse(search(request, &repository()).await),
         }
+    }
 
-        last_evaluated_key.is_some()
-    } {}
+    async fn get(pairs: &[(&str, &str)]) -> (u16, Value) {
+        respond(&query(pairs), None).await
+    }
 
-    result.sort_by(sort_func);
+    #[tokio::test]
+    async fn finds_names_containing_the_string() {
+        let (status, body) = get(&[("language", "en"), ("string", "Potion")]).await;
+        assert_eq!(status, 200);
+        assert_eq!(ids(&body), vec![4551, 4552]);
+        assert_eq!(
+            body["Results"][0]["MatchedLanguages"],
+            serde_json::json!(["en"])
+        );
+        assert_eq!(body["NextCursor"], Value::Null);
+    }
 
-    Ok(result)
+    #[tokio::test]
+    async fn searches_the_accepted_language_without_language() {
+        let query = query(&[("string", "Trank")]);
+        let (status, body) = respond(&query, Some("de-CH, en;q=0.5")).await;
+        assert_eq!(status, 200);
+        assert_eq!(ids(&body), vec![4551, 4552]);
+        assert_eq!(body["Condition"]["language"], "de");
+
+        let (status, _) = respond(&query, None).await;
+        assert_eq!(status, 400);
+    }
+
+    #[tokio::test]
+    async fn searches_several_languages_at_once() {
+        let (status, body) = get(&[("language", "fr,en"), ("string", "Potion")]).await;
+        assert_eq!(status, 200);
+        assert_eq!(ids(&body), vec![4551, 4552]);
+        assert_eq!(body["Condition"]["language"], "fr,en");
+        assert_eq!(
+            body["Results"][0]["MatchedLanguages"],
+            serde_json::json!(["fr", "en"])
+        );
+
+        let (status, body) = get(&[("language", "any"), ("string", "Trank")]).await;
+        assert_eq!(status, 200);
+        assert_eq!(ids(&body), vec![4551, 4552]);
+        assert_eq!(
+            body["Results"][0]["MatchedLanguages"],
+            serde_json::json!(["de"])
+        );
+
+        let (status, body) = get(&[("language", "en,xx"), ("string", "Potion")]).await;
+        assert_eq!(status, 400);
+        assert_eq!(body["type"], "BadRequest");
+    }
+
+    #[tokio::test]
+    async fn filters_by_category() {
+        let (status, body) = get(&[("language", "en"), ("string", "i")]).await;
+        assert_eq!(status, 200);
+        assert_eq!(ids(&body), vec![1, 1601, 4551, 4552, 12345]);
+
+        let (_, body) = get(&[("language", "en"), ("string", "i"), ("category", "43")]).await;
+        assert_eq!(ids(&body), vec![4551, 4552]);
+        assert_eq!(body["Condition"]["category"], serde_json::json!([43]));
+
+        let (_, body) = get(&[("language", "en"), ("string", "i"), ("marketable", "true")]).await;
+        assert_eq!(ids(&body), vec![1601, 4551, 4552, 12345]);
+
+        let (status, _) = get(&[("language", "en"), ("string", "i"), ("category", "abc")]).await;
+        assert_eq!(status, 400);
+        let (status, _) =
+            get(&[("language", "en"), ("string", "i"), ("marketable", "maybe")]).await;
+        assert_eq!(status, 400);
+    }
+
+    #[tokio::test]
+    async fn requires_string() {
+        let (status, body) = get(&[("language", "en")]).await;
+        assert_eq!(status, 400);
+        assert_eq!(body["type"], "BadRequest");
+    }
+
+    #[tokio::test]
+    async fn rejects_empty_strings() {
+        let (status, _) = get(&[("language", "en"), ("string", "")]).await;
+        assert_eq!(status, 400);
+        let (status, _) =
+            get(&[("language", "en"), ("string", "-"), ("match", "normalized")]).await;
+        assert_eq!(status, 400);
+        let (status, _) = get(&[("language", "en"), ("string", "-")]).await;
+        assert_eq!(status, 200);
+    }
+
+    #[tokio::test]
+    async fn fuzzy_search_forgives_typos() {
+        let (status, body) =
+            get(&[("language", "en"), ("string", "potoin"), ("mode", "fuzzy")]).await;
+        assert_eq!(status, 200);
+        assert_eq!(ids(&body), vec![4551, 4552]);
+        assert!(
+            body["Results"][0]["Score"].as_f64().unwrap()
+                > body["Results"][1]["Score"].as_f64().unwrap()
+        );
+
+        let (status, body) = get(&[("language", "en"), ("string", "a"), ("mode", "fuzzy")]).await;
+        assert_eq!(status, 200);
+        assert_eq!(ids(&body), Vec::<u64>::new());
+    }
+
+    #[tokio::test]
+    async fn pages_with_cursor() {
+        let (status, body) = get(&[("language", "en"), ("string", "o"), ("limit", "2")]).await;
+        assert_eq!(status, 200);
+        assert_eq!(ids(&body), vec![1601, 4551]);
+        let cursor = body["NextCursor"].as_str().unwrap().to_string();
+
+        let (status, body) = get(&[
+            ("language", "en"),
+            ("string", "o"),
+            ("limit", "2"),
+            ("cursor", &cursor),
+        ])
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(ids(&body), vec![4552]);
+        assert_eq!(body["NextCursor"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn returns_only_requested_fields() {
+        let (status, body) = get(&[
+            ("language", "ja"),
+            ("string", "ポーション"),
+            ("fields", "ID,Name_ja"),
+        ])
+        .await;
+        assert_eq!(status, 200);
+        let first = body["Results"][0].as_object().unwrap();
+        let mut keys: Vec<&str> = first.keys().map(|key| key.as_str()).collect();
+        keys.sort();
+        assert_eq!(keys, vec!["ID", "MatchedLanguages", "Name_ja"]);
+    }
 }
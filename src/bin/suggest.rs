@@ -0,0 +1,215 @@
+use env_logger;
+use lambda_http::{handler, lambda, Context, IntoResponse, Request, Response};
+use log::{info, warn};
+use std::collections::HashMap;
+
+use ffxiv_item_name_database_api::model::{
+    create_json_response, get_accept_language, parse_query, resolve_language,
+    vary_on_accept_language, HttpErrorType, Language, MatchMode,
+};
+use ffxiv_item_name_database_api::pagination::parse_limit;
+use ffxiv_item_name_database_api::repository::{
+    load_in_memory_repository, InMemoryItemRepository, ItemRepository,
+};
+use ffxiv_item_name_database_api::retry::invocation_deadline;
+use serde::Serialize;
+use std::str::FromStr;
+
+#[cfg(test)]
+#[path = "../../tests/common/mod.rs"]
+mod common;
+
+type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
+
+const DEFAULT_LIMIT: usize = 10;
+const MAX_LIMIT: usize = 50;
+
+#[derive(Debug, Serialize)]
+struct Condition {
+    prefix: String,
+    language: String,
+    #[serde(rename = "match")]
+    match_mode: String,
+    limit: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Suggestion {
+    #[serde(rename = "ID")]
+    id: u32,
+    #[serde(rename = "Name")]
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ResponseData {
+    condition: Condition,
+    results: Vec<Suggestion>,
+    warnings: usize,
+}
+
+struct SuggestCondition {
+    lang: Language,
+    prefix: String,
+    match_mode: MatchMode,
+    limit: usize,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    lambda::run(handler(lambda_handler)).await?;
+    Ok(())
+}
+
+async fn lambda_handler(event: Request, context: Context) -> Result<impl IntoResponse, Error> {
+    match env_logger::try_init() {
+        Err(e) => warn!("error occurred in env_logger::try_init(): {}", e),
+        Ok(_) => (),
+    };
+    info!("event: {:?}", event);
+    let query = parse_query(&event);
+    info!("query: {:?}", query);
+    let accept_language = get_accept_language(&event);
+    // A cold container scans the whole table, so bad queries are answered before that.
+    let condition = match parse_condition(&query, accept_language.as_deref()) {
+        Err(e) => return Ok(vary_on_accept_language(&query, e.create_response())),
+        Ok(condition) => condition,
+    };
+    let repository = match load_in_memory_repository(invocation_deadline(context.deadline)).await {
+        Err(e) => return Ok(e.create_response()),
+        Ok(repository) => repository,
+    };
+    let response = suggest(&condition, repository);
+    Ok(vary_on_accept_language(&query, response))
+}
+
+fn suggest(condition: &SuggestCondition, repository: &InMemoryItemRepository) -> Response<String> {
+    let results = repository
+        .suggest(
+            &condition.lang,
+            &condition.prefix,
+            &condition.match_mode,
+            condition.limit,
+        )
+        .into_iter()
+        .map(|item| Suggestion {
+            id: item.id,
+            name: item.get_name(&condition.lang),
+        })
+        .collect();
+    let body = ResponseData {
+        condition: Condition {
+            prefix: condition.prefix.clone(),
+            language: condition.lang.to_string(),
+            match_mode: condition.match_mode.to_string(),
+            limit: condition.limit,
+        },
+        results,
+        warnings: repository.warnings(),
+    };
+    create_json_response(&body, "ask for a lower limit.")
+}
+
+/// Without `language` the prefix is looked up in the language `accept_language` prefers.
+fn parse_condition(
+    query: &HashMap<String, String>,
+    accept_language: Option<&str>,
+) -> Result<SuggestCondition, HttpErrorType> {
+    let lang: Language = match resolve_language(query, accept_language) {
+        Err(e) => return Err(e),
+        Ok(lang) => lang,
+    };
+    let prefix: String = match query.get("prefix") {
+        None => return Err(HttpErrorType::BadRequest("prefix is required.".to_string())),
+        Some(prefix) => prefix.clone(),
+    };
+    // Type-ahead input is rarely typed with the exact case and accents,
+    // so prefixes are compared normalized unless asked otherwise.
+    let match_mode: MatchMode = match query.get("match") {
+        None => MatchMode::Normalized,
+        Some(mode) => match MatchMode::from_str(mode) {
+            Err(_) => {
+                return Err(HttpErrorType::BadRequest(format!(
+                    "match '{}' is invalid.",
+                    mode
+                )))
+            }
+            Ok(mode) => mode,
+        },
+    };
+    // Every name starts with the empty string, so it would suggest the first names of all.
+    if match_mode.apply(&lang, &prefix).is_empty() {
+        return Err(HttpErrorType::BadRequest(format!(
+            "prefix '{}' has nothing to search for.",
+            prefix
+        )));
+    }
+    let limit = match parse_limit(query, DEFAULT_LIMIT, MAX_LIMIT) {
+        Err(e) => return Err(e),
+        Ok(limit) => limit,
+    };
+    Ok(SuggestCondition {
+        lang,
+        prefix,
+        match_mode,
+        limit,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{parse, query, repository};
+    use serde_json::Value;
+
+    fn respond(query: &HashMap<String, String>, accept_language: Option<&str>) -> (u16, Value) {
+        match parse_condition(query, accept_language) {
+            Err(e) => parse(e.create_response()),
+            Ok(condition) => parse(suggest(&condition, &repository())),
+        }
+    }
+
+    fn get(pairs: &[(&str, &str)]) -> (u16, Value) {
+        respond(&query(pairs), None)
+    }
+
+    #[test]
+    fn suggests_names_starting_with_the_prefix() {
+        let (status, body) = get(&[("language", "en"), ("prefix", "e")]);
+        assert_eq!(status, 200);
+        assert_eq!(body["Results"][0]["Name"], "Eau de Vie");
+        assert_eq!(body["Results"][1]["Name"], "Ether");
+        assert_eq!(body["Condition"]["limit"], DEFAULT_LIMIT);
+    }
+
+    #[test]
+    fn suggests_in_the_accepted_language_without_language() {
+        let query = query(&[("prefix", "ポ")]);
+        let (status, body) = respond(&query, Some("ja-JP,ja;q=0.9"));
+        assert_eq!(status, 200);
+        assert_eq!(body["Condition"]["language"], "ja");
+        assert_eq!(body["Results"][0]["Name"], "ポーション");
+    }
+
+    #[test]
+    fn rejects_empty_prefixes() {
+        let (status, _) = get(&[("language", "en"), ("prefix", "")]);
+        assert_eq!(status, 400);
+        let (status, body) = get(&[("language", "en"), ("prefix", "-")]);
+        assert_eq!(status, 400);
+        assert_eq!(body["type"], "BadRequest");
+        let (status, _) = get(&[("language", "en"), ("prefix", "-"), ("match", "exact")]);
+        assert_eq!(status, 200);
+    }
+
+    #[test]
+    fn limits_suggestions() {
+        let (status, body) = get(&[("language", "en"), ("prefix", "e"), ("limit", "1")]);
+        assert_eq!(status, 200);
+        assert_eq!(body["Results"].as_array().unwrap().len(), 1);
+
+        let (status, _) = get(&[("language", "en"), ("prefix", "e"), ("limit", "51")]);
+        assert_eq!(status, 400);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::model::{HttpErrorType, Item};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Restricts results by `ItemSearchCategory`.
+///
+/// Items without a category cannot be sold on the market board, so
+/// `marketable` keeps only items whose category ID is not 0.
+#[derive(Debug, Serialize)]
+pub struct CategoryFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category: Option<Vec<u32>>,
+    marketable: bool,
+}
+
+impl CategoryFilter {
+    /// Reads `category` (comma separated IDs) and `marketable` from the query string.
+    pub fn parse(query: &HashMap<String, String>) -> Result<CategoryFilter, HttpErrorType> {
+        let category = match query.get("category") {
+            None => None,
+            Some(text) => {
+                let mut ids: Vec<u32> = Vec::new();
+                for raw in text.split(',') {
+                    match raw.parse::<u32>() {
+                        Err(_) => {
+                            return Err(HttpErrorType::BadRequest(
+                                "category must be comma separated numbers".to_string(),
+                            ))
+                        }
+                        Ok(id) => ids.push(id),
+                    }
+                }
+                Some(ids)
+            }
+        };
+        let marketable = match query.get("marketable").map(|text| text.as_str()) {
+            None | Some("false") => false,
+            Some("true") => true,
+            Some(text) => {
+                return Err(HttpErrorType::BadRequest(format!(
+                    "marketable '{}' is invalid.",
+                    text
+                )))
+            }
+        };
+        Ok(CategoryFilter {
+            category,
+            marketable,
+        })
+    }
+
+    pub fn matches(&self, item: &Item) -> bool {
+        let id = item.get_item_search_category_id();
+        if self.marketable && id == 0 {
+            return false;
+        }
+        match &self.category {
+            None => true,
+            Some(ids) => ids.contains(&id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn item(id: u32, category: Option<u32>) -> Item {
+        serde_json::from_value(serde_json::json!({
+            "ID": id,
+            "Icon": "",
+            "ItemSearchCategory": {"ID": category, "Name": null},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn keeps_items_of_the_given_categories() {
+        let filter = CategoryFilter::parse(&query(&[("category", "43,9")])).unwrap();
+        assert!(filter.matches(&item(4551, Some(43))));
+        assert!(filter.matches(&item(1601, Some(9))));
+        assert!(!filter.matches(&item(12345, Some(45))));
+        assert!(!filter.matches(&item(1, None)));
+
+        let filter = CategoryFilter::parse(&query(&[])).unwrap();
+        assert!(filter.matches(&item(1, None)));
+        assert!(filter.matches(&item(4551, Some(43))));
+    }
+
+    #[test]
+    fn marketable_drops_items_without_category() {
+        let filter = CategoryFilter::parse(&query(&[("marketable", "true")])).unwrap();
+        assert!(!filter.matches(&item(1, None)));
+        assert!(!filter.matches(&item(1, Some(0))));
+        assert!(filter.matches(&item(4551, Some(43))));
+
+        let filter = CategoryFilter::parse(&query(&[("marketable", "false")])).unwrap();
+        assert!(filter.matches(&item(1, None)));
+
+        let filter =
+            CategoryFilter::parse(&query(&[("category", "0"), ("marketable", "true")])).unwrap();
+        assert!(!filter.matches(&item(1, None)));
+    }
+
+    #[test]
+    fn rejects_malformed_values() {
+        for pairs in [
+            [("category", "abc")],
+            [("category", "43,")],
+            [("category", "-1")],
+            [("marketable", "maybe")],
+            [("marketable", "TRUE")],
+        ]
+        .iter()
+        {
+            assert!(CategoryFilter::parse(&query(pairs)).is_err(), "{:?}", pairs);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use crate::model::{Item, Language, MatchMode};
+use crate::repository::NameMatch;
+use std::cmp;
+
+/// Names scoring below this are not reported by fuzzy searches.
+pub const MIN_SCORE: f64 = 0.6;
+
+/// Queries shorter than this lean on the whole name instead, since a few letters are
+/// found somewhere in most names.
+const SHORT_QUERY_LEN: usize = 4;
+
+/// Edit distance between `a` and `b`.
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current: Vec<usize> = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == cb { 0 } else { 1 };
+            current[j + 1] = cmp::min(substitution, cmp::min(previous[j + 1], current[j]) + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Smallest edit distance between `needle` and any substring of `haystack`.
+fn substring_distance(needle: &[char], haystack: &[char]) -> usize {
+    let mut previous: Vec<usize> = vec![0; haystack.len() + 1];
+    let mut current: Vec<usize> = vec![0; haystack.len() + 1];
+    for (i, cn) in needle.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, ch) in haystack.iter().enumerate() {
+            let substitution = previous[j] + if cn == ch { 0 } else { 1 };
+            current[j + 1] = cmp::min(substitution, cmp::min(previous[j + 1], current[j]) + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous.into_iter().min().unwrap_or(0)
+}
+
+/// Rates how well `name` answers `query`, from 0.0 (unrelated) to 1.0 (identical).
+///
+/// Both strings are expected to be prepared by the same `MatchMode` already. The score
+/// mostly reflects how closely the query matches some part of the name, with the rest
+/// rewarding names of similar length, so "potoin" rates "Potion" above "Hi-Potion".
+/// Short queries mostly score against the whole name, so "a" does not match every name.
+/// Queries of several words also score by the share of words found in the name, which
+/// keeps reordered words like "potion hi" from being penalized as typos.
+pub fn score(query: &str, name: &str) -> f64 {
+    let q: Vec<char> = query.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    if q.is_empty() || n.is_empty() {
+        return 0.0;
+    }
+
+    let partial = 1.0 - substring_distance(&q, &n) as f64 / q.len() as f64;
+    let whole = 1.0 - levenshtein(&q, &n) as f64 / cmp::max(q.len(), n.len()) as f64;
+    let partial_weight = 0.75 * cmp::min(q.len(), SHORT_QUERY_LEN) as f64 / SHORT_QUERY_LEN as f64;
+    let edit = partial_weight * partial + (1.0 - partial_weight) * whole;
+
+    let query_tokens: Vec<&str> = query.split_whitespace().collect();
+    let tokens = if query_tokens.len() > 1 {
+        let name_tokens: Vec<&str> = name.split_whitespace().collect();
+        let found = query_tokens
+            .iter()
+            .filter(|token| name_tokens.contains(token))
+            .count();
+        0.9 * found as f64 / query_tokens.len() as f64
+    } else {
+        0.0
+    };
+
+    (edit.max(tokens) * 1000.0).round() / 1000.0
+}
+
+/// Scores `item` in each of `langs` and keeps it when any name reaches `MIN_SCORE`.
+pub fn match_item(
+    item: Item,
+    langs: &[Language],
+    string: &str,
+    mode: &MatchMode,
+) -> Option<NameMatch> {
+    let mut languages: Vec<Language> = Vec::new();
+    let mut best: f64 = 0.0;
+    for lang in langs {
+        let name = match item.name(lang) {
+            None => continue,
+            Some(name) => name,
+        };
+        let s = score(&mode.apply(lang, string), &mode.apply(lang, name));
+        if s >= MIN_SCORE {
+            languages.push(*lang);
+            best = best.max(s);
+        }
+    }
+    if languages.is_empty() {
+        None
+    } else {
+        Some(NameMatch {
+            item,
+            languages,
+            score: Some(best),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_names_score_one() {
+        assert_eq!(score("potion", "potion"), 1.0);
+        assert_eq!(score("a", "a"), 1.0);
+        assert_eq!(score("", "potion"), 0.0);
+    }
+
+    #[test]
+    fn prefers_names_of_similar_length() {
+        let potion = score("potoin", "potion");
+        let hi_potion = score("potoin", "hi potion");
+        assert!(potion >= MIN_SCORE);
+        assert!(hi_potion >= MIN_SCORE);
+        assert!(potion > hi_potion);
+        assert!(score("potoin", "elixir") < MIN_SCORE);
+    }
+
+    #[test]
+    fn short_queries_do_not_match_every_name() {
+        assert!(score("a", "bronze gladius") < MIN_SCORE);
+        assert!(score("o", "potion") < MIN_SCORE);
+        assert!(score("pot", "potion") >= MIN_SCORE);
+    }
+
+    #[test]
+    fn reordered_words_still_match() {
+        assert!(score("potion hi", "hi potion") >= 0.9);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::fuzzy;
+use crate::model::{Item, Language, MatchMode};
+use std::collections::{HashMap, HashSet};
+
+/// Substring index over the names of one language.
+///
+/// Every pair of adjacent characters in a name is mapped to the positions of
+/// the names containing it. A query is answered by taking the shortest posting
+/// list among the query's own pairs and verifying each candidate with
+/// `contains`, so only a fraction of the names is ever compared.
+///
+/// The positions are also kept sorted by name, which answers prefix queries
+/// with a binary search. Items without a name in the language hold an empty
+/// name, which never matches.
+struct NameIndex {
+    names: Vec<String>,
+    bigrams: HashMap<(char, char), Vec<usize>>,
+    sorted: Vec<usize>,
+}
+
+impl NameIndex {
+    fn build(names: Vec<String>) -> NameIndex {
+        let mut bigrams: HashMap<(char, char), Vec<usize>> = HashMap::new();
+        for (position, name) in names.iter().enumerate() {
+            let chars: Vec<char> = name.chars().collect();
+            let unique: HashSet<(char, char)> = chars.windows(2).map(|w| (w[0], w[1])).collect();
+            for bigram in unique {
+                bigrams.entry(bigram).or_default().push(position);
+            }
+        }
+        let mut sorted: Vec<usize> = (0..names.len()).collect();
+        sorted.sort_by(|a, b| names[*a].cmp(&names[*b]).then(a.cmp(b)));
+        NameIndex {
+            names,
+            bigrams,
+            sorted,
+        }
+    }
+
+    fn search(&self, string: &str) -> Vec<usize> {
+        let chars: Vec<char> = string.chars().collect();
+        if chars.len() < 2 {
+            return self.scan(string, 0..self.names.len());
+        }
+
+        let mut shortest: Option<&Vec<usize>> = None;
+        for window in chars.windows(2) {
+            match self.bigrams.get(&(window[0], window[1])) {
+                None => return Vec::new(),
+                Some(positions) => {
+                    if shortest.map_or(true, |s| positions.len() < s.len()) {
+                        shortest = Some(positions);
+                    }
+                }
+            }
+        }
+
+        match shortest {
+            None => Vec::new(),
+            Some(positions) => self.scan(string, positions.iter().cloned()),
+        }
+    }
+
+    fn prefix_search(&self, prefix: &str, limit: usize) -> Vec<usize> {
+        // first position in `sorted` whose name is not less than the prefix
+        let mut low = 0;
+        let mut high = self.sorted.len();
+        while low < high {
+            let middle = (low + high) / 2;
+            if self.names[self.sorted[middle]].as_str() < prefix {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        self.sorted[low..]
+            .iter()
+            .take_while(|position| self.names[**position].starts_with(prefix))
+            .filter(|position| !self.names[**position].is_empty())
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    fn fuzzy_search(&self, string: &str) -> Vec<(usize, f64)> {
+        self.names
+            .iter()
+            .enumerate()
+            .map(|(position, name)| (position, fuzzy::score(string, name)))
+            .filter(|(_, score)| *score >= fuzzy::MIN_SCORE)
+            .collect()
+    }
+
+    fn scan<I: Iterator<Item = usize>>(&self, string: &str, positions: I) -> Vec<usize> {
+        positions
+            .filter(|position| {
+                let name = &self.names[*position];
+                !name.is_empty() && name.contains(string)
+            })
+            .collect()
+    }
+}
+
+/// In-memory substring index over the names of a fixed list of items.
+///
+/// Names are indexed once per language and match mode, holding the names as
+/// `MatchMode::apply` prepares them. Search results are positions into the
+/// slice the index was built from, returned in ascending order.
+pub struct SearchIndex {
+    indexes: HashMap<(Language, MatchMode), NameIndex>,
+}
+
+impl SearchIndex {
+    pub fn build(items: &[Item]) -> SearchIndex {
+        let mut indexes = HashMap::new();
+        for lang in Language::all() {
+            for mode in [MatchMode::Exact, MatchMode::Normalized].iter() {
+                let names = items
+                    .iter()
+                    .map(|item| mode.apply(&lang, item.name(&lang).unwrap_or("")))
+                    .collect();
+                indexes.insert((lang, *mode), NameIndex::build(names));
+            }
+        }
+        SearchIndex { indexes }
+    }
+
+    pub fn search(&self, lang: &Language, string: &str, mode: &MatchMode) -> Vec<usize> {
+        match self.indexes.get(&(*lang, *mode)) {
+            None => Vec::new(),
+            Some(index) => index.search(&mode.apply(lang, string)),
+        }
+    }
+
+    /// Returns up to `limit` names starting with `prefix`, in the order of the prepared names.
+    pub fn prefix_search(
+        &self,
+        lang: &Language,
+        prefix: &str,
+        mode: &MatchMode,
+        limit: usize,
+    ) -> Vec<usize> {
+        match self.indexes.get(&(*lang, *mode)) {
+            None => Vec::new(),
+            Some(index) => index.prefix_search(&mode.apply(lang, prefix), limit),
+        }
+    }
+
+    /// Scores every name against `string`, keeping those reaching `fuzzy::MIN_SCORE`.
+    pub fn fuzzy_search(
+        &self,
+        lang: &Language,
+        string: &str,
+        mode: &MatchMode,
+    ) -> Vec<(usize, f64)> {
+        match self.indexes.get(&(*lang, *mode)) {
+            None => Vec::new(),
+            Some(index) => index.fuzzy_search(&mode.apply(lang, string)),
+        }
+    }
+}
@@ -1 +1,9 @@
+pub mod attribute;
+pub mod filter;
+pub mod fuzzy;
+pub mod index;
 pub mod model;
+pub mod pagination;
+pub mod projection;
+pub mod repository;
+pub mod retry;
@@ -1,18 +1,28 @@
+use crate::attribute::{from_attributes, to_attributes};
+use lambda_http::http::HeaderValue;
 use lambda_http::{Request, RequestExt, Response};
-use log::{error, info};
-use rusoto_dynamodb::AttributeValue;
-use serde::{Deserialize, Serialize};
+use log::{debug, error, warn};
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{AttributeValue, BatchGetItemError, GetItemError, ScanError};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
+use std::error::Error;
+use std::fmt;
 use std::str::FromStr;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Language {
     Deutsch,
     French,
     English,
     Japanese,
+    Chinese,
+    Korean,
 }
 
 impl FromStr for Language {
@@ -24,6 +34,8 @@ impl FromStr for Language {
             "fr" => Language::French,
             "en" => Language::English,
             "ja" => Language::Japanese,
+            "zh" => Language::Chinese,
+            "ko" => Language::Korean,
             _ => return Err("invalid lang code".to_string()),
         };
         Ok(name)
@@ -31,35 +43,305 @@ impl FromStr for Language {
 }
 
 impl Language {
+    pub fn all() -> Vec<Language> {
+        vec![
+            Language::Deutsch,
+            Language::French,
+            Language::English,
+            Language::Japanese,
+            Language::Chinese,
+            Language::Korean,
+        ]
+    }
+
     pub fn to_string(&self) -> String {
         let lang = match self {
             Language::Deutsch => "de",
             Language::French => "fr",
             Language::English => "en",
             Language::Japanese => "ja",
+            Language::Chinese => "zh",
+            Language::Korean => "ko",
         };
         lang.to_string()
     }
 
+    /// The supported language an `Accept-Language` header such as `fr-CA, en;q=0.8` prefers.
+    pub fn negotiate(accept_language: &str) -> Option<Language> {
+        let mut candidates: Vec<(f64, Language)> = Vec::new();
+        for entry in accept_language.split(',') {
+            let mut parts = entry.split(';').map(|part| part.trim());
+            let tag = match parts.next() {
+                None => continue,
+                Some(tag) => tag,
+            };
+            let mut quality = 1.0;
+            for param in parts {
+                let value = match param
+                    .strip_prefix("q=")
+                    .or_else(|| param.strip_prefix("Q="))
+                {
+                    None => continue,
+                    Some(value) => value,
+                };
+                quality = match value.parse::<f64>() {
+                    Ok(q) if (0.0..=1.0).contains(&q) => q,
+                    _ => 0.0,
+                };
+            }
+            if quality <= 0.0 {
+                continue;
+            }
+            let primary = tag.split(|c| c == '-' || c == '_').next().unwrap_or("");
+            match Language::from_str(&primary.to_lowercase()) {
+                Err(_) => continue,
+                Ok(lang) => candidates.push((quality, lang)),
+            }
+        }
+        // stable, so equally preferred languages keep the order the client sent them in
+        candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+        candidates.first().map(|(_, lang)| *lang)
+    }
+
     pub fn get_key(&self) -> String {
         let key = match self {
             Language::Deutsch => "Name_de",
             Language::French => "Name_fr",
             Language::English => "Name_en",
             Language::Japanese => "Name_ja",
+            Language::Chinese => "Name_zh",
+            Language::Korean => "Name_ko",
         };
         key.to_string()
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MatchMode {
+    Exact,
+    Normalized,
+}
+
+impl FromStr for MatchMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mode = match s {
+            "exact" => MatchMode::Exact,
+            "normalized" => MatchMode::Normalized,
+            _ => return Err("invalid match mode".to_string()),
+        };
+        Ok(mode)
+    }
+}
+
+impl MatchMode {
+    pub fn to_string(&self) -> String {
+        let mode = match self {
+            MatchMode::Exact => "exact",
+            MatchMode::Normalized => "normalized",
+        };
+        mode.to_string()
+    }
+
+    /// Prepares a name or a query string for comparison under this mode.
+    pub fn apply(&self, lang: &Language, text: &str) -> String {
+        match self {
+            MatchMode::Exact => text.to_string(),
+            MatchMode::Normalized => normalize_name(lang, text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    Substring,
+    Fuzzy,
+}
+
+impl FromStr for SearchMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mode = match s {
+            "substring" => SearchMode::Substring,
+            "fuzzy" => SearchMode::Fuzzy,
+            _ => return Err("invalid search mode".to_string()),
+        };
+        Ok(mode)
+    }
+}
+
+impl SearchMode {
+    pub fn to_string(&self) -> String {
+        let mode = match self {
+            SearchMode::Substring => "substring",
+            SearchMode::Fuzzy => "fuzzy",
+        };
+        mode.to_string()
+    }
+}
+
+/// What to do with a stored item that cannot be converted into an `Item`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodePolicy {
+    /// Fail the whole request.
+    Strict,
+    /// Skip the item, log it and count it as a warning.
+    Lenient,
+}
+
+impl FromStr for DecodePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let policy = match s {
+            "strict" => DecodePolicy::Strict,
+            "lenient" => DecodePolicy::Lenient,
+            _ => return Err("invalid decode policy".to_string()),
+        };
+        Ok(policy)
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c.is_whitespace()
+        || matches!(
+            c,
+            '-' | '_' | '\u{2010}' | '\u{2011}' | '\u{2012}' | '\u{2013}' | '\u{2014}'
+        )
+}
+
+/// Folds case, accents and separators, so "eau de vie" matches "Eau-de-Vie".
+pub fn normalize_name(lang: &Language, text: &str) -> String {
+    match lang {
+        Language::Japanese => return normalize_japanese(text),
+        Language::Chinese | Language::Korean => return normalize_cjk(text),
+        _ => (),
+    }
+    let mut result = String::with_capacity(text.len());
+    let mut pending_separator = false;
+    for c in text.nfkd() {
+        if is_combining_mark(c) {
+            continue;
+        }
+        if is_separator(c) {
+            pending_separator = !result.is_empty();
+            continue;
+        }
+        if pending_separator {
+            result.push(' ');
+            pending_separator = false;
+        }
+        match c {
+            'ß' => result.push_str("ss"),
+            'æ' | 'Æ' => result.push_str("ae"),
+            'œ' | 'Œ' => result.push_str("oe"),
+            '\u{2019}' => result.push('\''),
+            _ => result.extend(c.to_lowercase()),
+        }
+    }
+    result
+}
+
+/// NFKC keeps Hangul syllables composed, unlike the NFKD used for Latin text.
+fn normalize_cjk(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut pending_separator = false;
+    for c in text.nfkc() {
+        if is_separator(c) {
+            pending_separator = !result.is_empty();
+            continue;
+        }
+        if pending_separator {
+            result.push(' ');
+            pending_separator = false;
+        }
+        result.extend(c.to_lowercase());
+    }
+    result
+}
+
+fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3041}'..='\u{3096}' | '\u{30A1}'..='\u{30FA}' | '\u{30FC}')
+}
+
+fn is_long_vowel_variant(c: char) -> bool {
+    matches!(
+        c,
+        '-' | '~' | '\u{2010}'..='\u{2015}' | '\u{2212}' | '\u{301C}' | '\u{30FC}'
+    )
+}
+
+/// Maps hiragana to the katakana with the same sound.
+fn to_katakana(c: char) -> char {
+    match c {
+        '\u{3041}'..='\u{3096}' | '\u{309D}' | '\u{309E}' => {
+            std::char::from_u32(c as u32 + 0x60).unwrap_or(c)
+        }
+        _ => c,
+    }
+}
+
+/// Folds script and width, so "はいぽーしょん" and "ﾊｲ・ﾎﾟｰｼｮﾝ" match "ハイポーション".
+pub fn normalize_japanese(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.nfkc() {
+        if c == '\u{30FB}' {
+            continue;
+        }
+        if is_long_vowel_variant(c) && result.chars().next_back().map_or(false, is_kana) {
+            result.push('\u{30FC}');
+            continue;
+        }
+        if is_separator(c) {
+            continue;
+        }
+        result.extend(to_katakana(c).to_lowercase());
+    }
+    result
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct ItemSearchCategory {
-    #[serde(rename = "ID")]
+    #[serde(rename = "ID", default, deserialize_with = "deserialize_category_id")]
     pub id: Option<u32>,
-    #[serde(rename = "Name")]
+    #[serde(
+        rename = "Name",
+        default,
+        deserialize_with = "deserialize_category_name"
+    )]
     pub name: Option<String>,
 }
 
+/// Reads an ID stored as anything but a number as `None`, like items always have been.
+fn deserialize_category_id<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u32>, D::Error> {
+    match Value::deserialize(deserializer) {
+        Err(e) => Err(e),
+        Ok(Value::Number(number)) => match number.as_u64() {
+            Some(id) if id <= u64::from(u32::MAX) => Ok(Some(id as u32)),
+            _ => Err(serde::de::Error::custom(format!(
+                "'{}' is not a valid ID",
+                number
+            ))),
+        },
+        Ok(_) => Ok(None),
+    }
+}
+
+/// Reads a name stored as anything but a string as `None`, like items always have been.
+fn deserialize_category_name<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    match Value::deserialize(deserializer) {
+        Err(e) => Err(e),
+        Ok(Value::String(name)) => Ok(Some(name)),
+        Ok(_) => Ok(None),
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Item {
     #[serde(rename = "ID")]
@@ -68,28 +350,60 @@ pub struct Item {
     pub icon: String,
     #[serde(rename = "ItemSearchCategory")]
     pub item_search_category: ItemSearchCategory,
-    #[serde(rename = "Name_de")]
-    pub name_de: String,
-    #[serde(rename = "Name_en")]
-    pub name_en: String,
-    #[serde(rename = "Name_fr")]
-    pub name_fr: String,
-    #[serde(rename = "Name_ja")]
-    pub name_ja: String,
-    #[serde(rename = "EorzeaDatabaseId")]
-    pub eorzea_database_id: String,
+    #[serde(rename = "Name_de", skip_serializing_if = "Option::is_none")]
+    pub name_de: Option<String>,
+    #[serde(rename = "Name_en", skip_serializing_if = "Option::is_none")]
+    pub name_en: Option<String>,
+    #[serde(rename = "Name_fr", skip_serializing_if = "Option::is_none")]
+    pub name_fr: Option<String>,
+    #[serde(rename = "Name_ja", skip_serializing_if = "Option::is_none")]
+    pub name_ja: Option<String>,
+    #[serde(rename = "Name_zh", skip_serializing_if = "Option::is_none")]
+    pub name_zh: Option<String>,
+    #[serde(rename = "Name_ko", skip_serializing_if = "Option::is_none")]
+    pub name_ko: Option<String>,
+    #[serde(rename = "EorzeaDatabaseId", skip_serializing_if = "Option::is_none")]
+    pub eorzea_database_id: Option<String>,
 }
 
 impl Item {
-    pub fn get_name(&self, language: &Language) -> String {
+    /// The name in `language`, or `None` while it is not translated.
+    pub fn name(&self, language: &Language) -> Option<&str> {
         let name = match language {
             Language::Japanese => &self.name_ja,
             Language::English => &self.name_en,
             Language::French => &self.name_fr,
             Language::Deutsch => &self.name_de,
+            Language::Chinese => &self.name_zh,
+            Language::Korean => &self.name_ko,
         };
-        name.clone()
+        match name {
+            Some(name) if !name.is_empty() => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The name to show in `language`. While it is not translated, the English name is
+    /// shown instead, then the Japanese one, then any other. Empty without any name.
+    pub fn get_name(&self, language: &Language) -> String {
+        let fallbacks = [*language, Language::English, Language::Japanese];
+        fallbacks
+            .iter()
+            .chain(Language::all().iter())
+            .filter_map(|lang| self.name(lang))
+            .next()
+            .unwrap_or("")
+            .to_string()
+    }
+
+    /// Languages the item has a name in.
+    pub fn available_languages(&self) -> Vec<Language> {
+        Language::all()
+            .into_iter()
+            .filter(|lang| self.name(lang).is_some())
+            .collect()
     }
+
     pub fn get_item_search_category_id(&self) -> u32 {
         match self.item_search_category.id {
             Some(num) => num,
@@ -98,6 +412,35 @@ impl Item {
     }
 }
 
+/// Items without a category are counted under ID 0.
+#[derive(Serialize, Debug, Clone)]
+pub struct CategorySummary {
+    #[serde(rename = "ID")]
+    pub id: u32,
+    #[serde(rename = "Name")]
+    pub name: Option<String>,
+    #[serde(rename = "ItemCount")]
+    pub item_count: usize,
+}
+
+/// Lists every distinct category of `items`, ordered by ID.
+pub fn summarize_categories(items: &[Item]) -> Vec<CategorySummary> {
+    let mut summaries: BTreeMap<u32, CategorySummary> = BTreeMap::new();
+    for item in items {
+        let id = item.get_item_search_category_id();
+        let summary = summaries.entry(id).or_insert_with(|| CategorySummary {
+            id,
+            name: None,
+            item_count: 0,
+        });
+        if summary.name.is_none() {
+            summary.name = item.item_search_category.name.clone();
+        }
+        summary.item_count += 1;
+    }
+    summaries.values().cloned().collect()
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct ErrorBody {
     #[serde(rename = "type")]
@@ -120,23 +463,133 @@ fn create_response(status: u16, error: &str, message: Option<String>) -> Respons
         .unwrap()
 }
 
+/// Lambda refuses to return response payloads larger than 6 MB.
+pub const MAX_RESPONSE_BYTES: usize = 6 * 1000 * 1000;
+
+#[derive(Debug)]
 pub enum HttpErrorType {
     BadRequest(String),
+    NotFound(String),
+    PayloadTooLarge(String),
+    TooManyRequests(String),
     InternalServerError(String),
+    ServiceUnavailable(String),
 }
 
 impl HttpErrorType {
-    pub fn create_response(&self) -> Response<String> {
+    /// The error for DynamoDB throttling that outlasted every retry.
+    pub fn throttled() -> HttpErrorType {
+        HttpErrorType::TooManyRequests("too many requests, please retry later.".to_string())
+    }
+
+    pub fn status_code(&self) -> u16 {
         match self {
-            HttpErrorType::InternalServerError(message) => {
+            HttpErrorType::BadRequest(_) => 400,
+            HttpErrorType::NotFound(_) => 404,
+            HttpErrorType::PayloadTooLarge(_) => 413,
+            HttpErrorType::TooManyRequests(_) => 429,
+            HttpErrorType::InternalServerError(_) => 500,
+            HttpErrorType::ServiceUnavailable(_) => 503,
+        }
+    }
+
+    /// The `type` of the error body. Clients branch on it, so it must never change.
+    pub fn type_code(&self) -> &'static str {
+        match self {
+            HttpErrorType::BadRequest(_) => "BadRequest",
+            HttpErrorType::NotFound(_) => "NotFound",
+            HttpErrorType::PayloadTooLarge(_) => "PayloadTooLarge",
+            HttpErrorType::TooManyRequests(_) => "TooManyRequests",
+            HttpErrorType::InternalServerError(_) => "InternalServerError",
+            HttpErrorType::ServiceUnavailable(_) => "ServiceUnavailable",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            HttpErrorType::BadRequest(message)
+            | HttpErrorType::NotFound(message)
+            | HttpErrorType::PayloadTooLarge(message)
+            | HttpErrorType::TooManyRequests(message)
+            | HttpErrorType::InternalServerError(message)
+            | HttpErrorType::ServiceUnavailable(message) => message,
+        }
+    }
+
+    /// Builds the error response. Messages of server side errors may reveal internals,
+    /// so they are only logged.
+    pub fn create_response(&self) -> Response<String> {
+        let message = match self {
+            HttpErrorType::InternalServerError(message)
+            | HttpErrorType::ServiceUnavailable(message) => {
                 error!("{}", message);
-                create_response(500, "InternalServerError", Option::None)
+                Option::None
             }
-            HttpErrorType::BadRequest(message) => {
-                create_response(400, "BadRequest", Some(message.clone()))
+            _ => Some(self.message().to_string()),
+        };
+        create_response(self.status_code(), self.type_code(), message)
+    }
+}
+
+impl fmt::Display for HttpErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.type_code(), self.message())
+    }
+}
+
+impl Error for HttpErrorType {}
+
+macro_rules! from_rusoto_error {
+    ($name:ident) => {
+        impl From<RusotoError<$name>> for HttpErrorType {
+            fn from(error: RusotoError<$name>) -> Self {
+                match error {
+                    RusotoError::Service($name::ProvisionedThroughputExceeded(message))
+                    | RusotoError::Service($name::RequestLimitExceeded(message)) => {
+                        warn!("throttled by DynamoDB: {}", message);
+                        HttpErrorType::throttled()
+                    }
+                    RusotoError::Unknown(ref response) if response.status.as_u16() == 429 => {
+                        warn!("throttled by DynamoDB: {}", error);
+                        HttpErrorType::throttled()
+                    }
+                    RusotoError::Service($name::InternalServerError(_))
+                    | RusotoError::HttpDispatch(_) => {
+                        HttpErrorType::ServiceUnavailable(error.to_string())
+                    }
+                    RusotoError::Unknown(ref response) if response.status.is_server_error() => {
+                        HttpErrorType::ServiceUnavailable(error.to_string())
+                    }
+                    _ => HttpErrorType::InternalServerError(error.to_string()),
+                }
             }
         }
+    };
+}
+
+from_rusoto_error!(ScanError);
+from_rusoto_error!(BatchGetItemError);
+from_rusoto_error!(GetItemError);
+
+/// Serializes `data` into a 200 response, or refuses with `PayloadTooLarge` when it is too
+/// large for Lambda to return. `hint` tells the client how to ask for less.
+pub fn create_json_response<T: Serialize>(data: &T, hint: &str) -> Response<String> {
+    let json = serde_json::to_string(data).unwrap();
+    if json.len() > MAX_RESPONSE_BYTES {
+        return HttpErrorType::PayloadTooLarge(format!(
+            "response would be {} bytes, more than {} bytes. {}",
+            json.len(),
+            MAX_RESPONSE_BYTES,
+            hint
+        ))
+        .create_response();
     }
+    Response::builder()
+        .status(200)
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Content-Type", "application/json")
+        .body(json)
+        .expect("failed")
 }
 
 pub fn parse_query(event: &Request) -> HashMap<String, String> {
@@ -149,6 +602,87 @@ pub fn parse_query(event: &Request) -> HashMap<String, String> {
     map
 }
 
+pub fn get_accept_language(event: &Request) -> Option<String> {
+    match event.headers().get("Accept-Language") {
+        None => None,
+        Some(value) => value.to_str().ok().map(|value| value.to_string()),
+    }
+}
+
+/// Parses `any` or a comma separated list of language codes such as `ja,en`.
+pub fn parse_languages(text: &str) -> Result<Vec<Language>, HttpErrorType> {
+    if text == "any" {
+        return Ok(Language::all());
+    }
+    let mut langs: Vec<Language> = Vec::new();
+    for code in text.split(',') {
+        let lang = match Language::from_str(code) {
+            Err(_) => {
+                return Err(HttpErrorType::BadRequest(format!(
+                    "language '{}' is invalid.",
+                    code
+                )))
+            }
+            Ok(lang) => lang,
+        };
+        if !langs.contains(&lang) {
+            langs.push(lang);
+        }
+    }
+    Ok(langs)
+}
+
+fn negotiate_language(accept_language: Option<&str>) -> Result<Language, HttpErrorType> {
+    match accept_language.and_then(Language::negotiate) {
+        None => Err(HttpErrorType::BadRequest(
+            "language is required unless Accept-Language names a supported language.".to_string(),
+        )),
+        Some(lang) => Ok(lang),
+    }
+}
+
+/// The language `language` in `query` names, or without it the one `accept_language` prefers.
+pub fn resolve_language(
+    query: &HashMap<String, String>,
+    accept_language: Option<&str>,
+) -> Result<Language, HttpErrorType> {
+    match query.get("language") {
+        None => negotiate_language(accept_language),
+        Some(code) => match Language::from_str(code) {
+            Err(_) => Err(HttpErrorType::BadRequest(format!(
+                "language '{}' is invalid.",
+                code
+            ))),
+            Ok(lang) => Ok(lang),
+        },
+    }
+}
+
+/// Same as `resolve_language`, but `language` may name several languages, see `parse_languages`.
+pub fn resolve_languages(
+    query: &HashMap<String, String>,
+    accept_language: Option<&str>,
+) -> Result<Vec<Language>, HttpErrorType> {
+    match query.get("language") {
+        None => negotiate_language(accept_language).map(|lang| vec![lang]),
+        Some(text) => parse_languages(text),
+    }
+}
+
+/// Tells caches that `response` depends on `Accept-Language` when `query` left the language
+/// to it.
+pub fn vary_on_accept_language(
+    query: &HashMap<String, String>,
+    mut response: Response<String>,
+) -> Response<String> {
+    if !query.contains_key("language") {
+        response
+            .headers_mut()
+            .insert("Vary", HeaderValue::from_static("Accept-Language"));
+    }
+    response
+}
+
 pub fn get_table_name() -> Result<String, HttpErrorType> {
     match env::var("TABLE_NAME") {
         Err(e) => Err(HttpErrorType::InternalServerError(format!(
@@ -162,161 +696,35 @@ pub fn get_table_name() -> Result<String, HttpErrorType> {
 pub fn convert_dynamodb_item_to_item(
     item: &HashMap<String, AttributeValue>,
 ) -> Result<Item, HttpErrorType> {
-    info!("Item: {:?}", item);
-    let item_search_category = match item.get("ItemSearchCategory") {
-        None => {
-            return Err(HttpErrorType::InternalServerError(
-                "ItemSearchCategory does not exist.".to_string(),
-            ))
-        }
-        Some(attr) => match &attr.m {
-            None => {
-                return Err(HttpErrorType::InternalServerError(
-                    "ItemSearchCategory does not exist.".to_string(),
-                ))
-            }
-            Some(map) => map,
-        },
-    };
-    Ok(Item {
-        id: match item.get("ID") {
-            None => {
-                return Err(HttpErrorType::InternalServerError(
-                    "ID does not exist.".to_string(),
-                ))
-            }
-            Some(id) => match &id.n {
-                None => {
-                    return Err(HttpErrorType::InternalServerError(
-                        "ID does not exist".to_string(),
-                    ))
-                }
-                Some(id) => match id.parse::<u32>() {
-                    Err(e) => {
-                        return Err(HttpErrorType::InternalServerError(format!(
-                            "ID '{}' failed to parse::<u32>(): {}",
-                            id, e
-                        )))
-                    }
-                    Ok(id) => id,
-                },
-            },
-        },
-        icon: match item.get("Icon") {
-            None => {
-                return Err(HttpErrorType::InternalServerError(
-                    "Icon does not exist.".to_string(),
-                ))
-            }
-            Some(icon) => match &icon.s {
-                None => {
-                    return Err(HttpErrorType::InternalServerError(
-                        "Icon does not exist.".to_string(),
-                    ))
-                }
-                Some(icon) => icon.clone(),
-            },
-        },
-        item_search_category: ItemSearchCategory {
-            id: match item_search_category.get("ID") {
-                None => None,
-                Some(id) => match &id.n {
-                    None => None,
-                    Some(id) => match id.parse::<u32>() {
-                        Err(e) => {
-                            return Err(HttpErrorType::InternalServerError(format!(
-                                "ItemSearchCategory.ID '{}' failed to parse::<u32>(): {}",
-                                id, e
-                            )))
-                        }
-                        Ok(id) => Some(id),
-                    },
-                },
-            },
-            name: match item_search_category.get("Name") {
-                None => None,
-                Some(name) => match &name.s {
-                    None => None,
-                    Some(name) => Some(name.clone()),
-                },
-            },
-        },
-        name_de: match item.get("Name_de") {
-            None => {
-                return Err(HttpErrorType::InternalServerError(
-                    "Name_de does not exist.".to_string(),
-                ))
-            }
-            Some(name) => match &name.s {
-                None => {
-                    return Err(HttpErrorType::InternalServerError(
-                        "Name_de does not exist.".to_string(),
-                    ))
-                }
-                Some(name) => name.clone(),
-            },
-        },
-        name_en: match item.get("Name_en") {
-            None => {
-                return Err(HttpErrorType::InternalServerError(
-                    "Name_en does not exist.".to_string(),
-                ))
-            }
-            Some(name) => match &name.s {
-                None => {
-                    return Err(HttpErrorType::InternalServerError(
-                        "Name_en does not exist.".to_string(),
-                    ))
-                }
-                Some(name) => name.clone(),
-            },
-        },
-        name_fr: match item.get("Name_fr") {
-            None => {
-                return Err(HttpErrorType::InternalServerError(
-                    "Name_fr does not exist.".to_string(),
-                ))
-            }
-            Some(name) => match &name.s {
-                None => {
-                    return Err(HttpErrorType::InternalServerError(
-                        "Name_fr does not exist.".to_string(),
-                    ))
-                }
-                Some(name) => name.clone(),
-            },
-        },
-        name_ja: match item.get("Name_ja") {
-            None => {
-                return Err(HttpErrorType::InternalServerError(
-                    "Name_ja does not exist.".to_string(),
-                ))
-            }
-            Some(name) => match &name.s {
-                None => {
-                    return Err(HttpErrorType::InternalServerError(
-                        "Name_ja does not exist.".to_string(),
-                    ))
-                }
-                Some(name) => name.clone(),
-            },
-        },
-        eorzea_database_id: match item.get("EorzeaDatabaseId") {
-            None => {
-                return Err(HttpErrorType::InternalServerError(
-                    "EorzeaDatabaseId does not exist.".to_string(),
-                ))
-            }
-            Some(name) => match &name.s {
-                None => {
-                    return Err(HttpErrorType::InternalServerError(
-                        "EorzeaDatabaseId does not exist.".to_string(),
-                    ))
-                }
-                Some(name) => name.clone(),
-            },
-        },
-    })
+    debug!("Item: {:?}", item);
+    match from_attributes::<Item>(item) {
+        Err(e) => Err(HttpErrorType::InternalServerError(format!(
+            "failed to convert item {}: {}",
+            describe_id(item),
+            e
+        ))),
+        Ok(item) => Ok(item),
+    }
+}
+
+pub fn convert_item_to_dynamodb_item(
+    item: &Item,
+) -> Result<HashMap<String, AttributeValue>, HttpErrorType> {
+    match to_attributes(item) {
+        Err(e) => Err(HttpErrorType::InternalServerError(format!(
+            "failed to convert item {}: {}",
+            item.id, e
+        ))),
+        Ok(attributes) => Ok(attributes),
+    }
+}
+
+/// The `ID` of a DynamoDB item for messages, even when it cannot be converted.
+fn describe_id(item: &HashMap<String, AttributeValue>) -> String {
+    match item.get("ID").and_then(|id| id.n.as_ref()) {
+        None => "(no ID)".to_string(),
+        Some(id) => id.clone(),
+    }
 }
 
 pub fn sort_func(a: &Item, b: &Item) -> Ordering {
@@ -328,3 +736,227 @@ pub fn sort_func(a: &Item, b: &Item) -> Ordering {
         _ => order,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_latin_names() {
+        let fold = |text| normalize_name(&Language::French, text);
+        assert_eq!(fold("Eau-de-Vie"), "eau de vie");
+        assert_eq!(fold("  eau  de -- vie "), "eau de vie");
+        assert_eq!(fold("Épée"), "epee");
+        assert_eq!(fold("Œil de Tigre"), "oeil de tigre");
+        assert_eq!(fold("Äther"), normalize_name(&Language::Deutsch, "ather"));
+        assert_eq!(normalize_name(&Language::Deutsch, "Straße"), "strasse");
+        assert_eq!(
+            normalize_name(&Language::English, "Kobold\u{2019}s"),
+            "kobold's"
+        );
+    }
+
+    #[test]
+    fn normalizes_chinese_and_korean_names() {
+        assert_eq!(normalize_name(&Language::Chinese, "ＨＱ 药水"), "hq 药水");
+        assert_eq!(normalize_name(&Language::Korean, "하이-포션"), "하이 포션");
+    }
+
+    #[test]
+    fn normalizes_japanese_script_and_width() {
+        let fold = |text| normalize_name(&Language::Japanese, text);
+        assert_eq!(fold("ハイポーション"), "ハイポーション");
+        assert_eq!(fold("はいぽーしょん"), "ハイポーション");
+        assert_eq!(fold("ﾊｲﾎﾟｰｼｮﾝ"), "ハイポーション");
+        assert_eq!(fold("ハイ・ポ－ション"), "ハイポーション");
+        assert_eq!(fold("ポ~ション"), "ポーション");
+        assert_eq!(fold("ＨＱ ポーション"), "hqポーション");
+        assert_eq!(fold("-ポーション"), "ポーション");
+    }
+
+    #[test]
+    fn summarizes_categories_by_id() {
+        let item = |id: u32, category: Option<u32>, name: Option<&str>| Item {
+            id,
+            icon: String::new(),
+            item_search_category: ItemSearchCategory {
+                id: category,
+                name: name.map(|name| name.to_string()),
+            },
+            name_de: None,
+            name_en: None,
+            name_fr: None,
+            name_ja: None,
+            name_zh: None,
+            name_ko: None,
+            eorzea_database_id: None,
+        };
+        let summaries = summarize_categories(&[
+            item(1, Some(43), None),
+            item(2, Some(43), Some("Medicine")),
+            item(3, None, None),
+            item(4, Some(9), Some("Gladiator's Arms")),
+        ]);
+        let counts: Vec<(u32, Option<&str>, usize)> = summaries
+            .iter()
+            .map(|s| (s.id, s.name.as_deref(), s.item_count))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                (0, None, 1),
+                (9, Some("Gladiator's Arms"), 1),
+                (43, Some("Medicine"), 2)
+            ]
+        );
+    }
+
+    fn number(n: &str) -> AttributeValue {
+        AttributeValue {
+            n: Some(n.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn string(s: &str) -> AttributeValue {
+        AttributeValue {
+            s: Some(s.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn category(id: AttributeValue, name: AttributeValue) -> HashMap<String, AttributeValue> {
+        let mut category = HashMap::new();
+        category.insert("ID".to_string(), id);
+        category.insert("Name".to_string(), name);
+        let mut item = HashMap::new();
+        item.insert("ID".to_string(), number("4551"));
+        item.insert("Icon".to_string(), string("/i/020000/020001.png"));
+        item.insert(
+            "ItemSearchCategory".to_string(),
+            AttributeValue {
+                m: Some(category),
+                ..Default::default()
+            },
+        );
+        item.insert("Name_en".to_string(), string("Potion"));
+        item
+    }
+
+    #[test]
+    fn items_survive_dynamodb_conversion() {
+        let item: Item = serde_json::from_value(serde_json::json!({
+            "ID": 4551,
+            "Icon": "/i/020000/020001.png",
+            "ItemSearchCategory": {"ID": 43, "Name": "Medicine"},
+            "Name_en": "Potion",
+            "Name_ja": "ポーション",
+            "Name_ko": "포션",
+            "EorzeaDatabaseId": "6f8a1a6d3b3"
+        }))
+        .unwrap();
+        let attributes = convert_item_to_dynamodb_item(&item).unwrap();
+        assert_eq!(attributes["ID"].n.as_deref(), Some("4551"));
+        assert!(!attributes.contains_key("Name_de"));
+        let converted = convert_dynamodb_item_to_item(&attributes).unwrap();
+        assert_eq!(
+            serde_json::to_value(&converted).unwrap(),
+            serde_json::to_value(&item).unwrap()
+        );
+    }
+
+    #[test]
+    fn tolerates_categories_of_other_types() {
+        let item = convert_dynamodb_item_to_item(&category(string("43"), number("1"))).unwrap();
+        assert_eq!(item.item_search_category.id, None);
+        assert_eq!(item.item_search_category.name, None);
+
+        let item =
+            convert_dynamodb_item_to_item(&category(number("43"), string("Medicine"))).unwrap();
+        assert_eq!(item.item_search_category.id, Some(43));
+        assert_eq!(item.item_search_category.name.as_deref(), Some("Medicine"));
+
+        let error =
+            convert_dynamodb_item_to_item(&category(number("-1"), string("Medicine"))).unwrap_err();
+        assert!(error.message().contains("ItemSearchCategory.ID"));
+    }
+
+    #[test]
+    fn negotiates_the_preferred_language() {
+        assert_eq!(Language::negotiate("ja"), Some(Language::Japanese));
+        assert_eq!(
+            Language::negotiate("fr-CA, fr;q=0.9, en;q=0.8"),
+            Some(Language::French)
+        );
+        assert_eq!(
+            Language::negotiate("en;q=0.5, de;q=0.8"),
+            Some(Language::Deutsch)
+        );
+        assert_eq!(Language::negotiate("zh-Hant-TW"), Some(Language::Chinese));
+        assert_eq!(Language::negotiate("KO_kr"), Some(Language::Korean));
+        assert_eq!(Language::negotiate("es, en;q=0.1"), Some(Language::English));
+        assert_eq!(
+            Language::negotiate("de, en;Q=1, ja"),
+            Some(Language::Deutsch)
+        );
+    }
+
+    #[test]
+    fn negotiation_skips_unusable_entries() {
+        assert_eq!(
+            Language::negotiate("ja;q=0, en;q=0.1"),
+            Some(Language::English)
+        );
+        assert_eq!(
+            Language::negotiate("ja;q=2, en;q=0.1"),
+            Some(Language::English)
+        );
+        assert_eq!(Language::negotiate("ja;q=abc"), None);
+        assert_eq!(Language::negotiate("*"), None);
+        assert_eq!(Language::negotiate("es-ES"), None);
+        assert_eq!(Language::negotiate(""), None);
+    }
+
+    #[test]
+    fn resolves_language_from_query_before_header() {
+        let mut query = HashMap::new();
+        assert_eq!(
+            resolve_language(&query, Some("ko")).unwrap(),
+            Language::Korean
+        );
+        assert!(resolve_language(&query, Some("es")).is_err());
+        assert!(resolve_language(&query, None).is_err());
+
+        query.insert("language".to_string(), "en".to_string());
+        assert_eq!(
+            resolve_language(&query, Some("ko")).unwrap(),
+            Language::English
+        );
+        query.insert("language".to_string(), "ja,en".to_string());
+        assert!(resolve_language(&query, None).is_err());
+        assert_eq!(
+            resolve_languages(&query, Some("ko")).unwrap(),
+            vec![Language::Japanese, Language::English]
+        );
+    }
+
+    #[test]
+    fn varies_only_when_the_header_chose_the_language() {
+        let response = || Response::new(String::new());
+        let mut query = HashMap::new();
+        let varied = vary_on_accept_language(&query, response());
+        assert_eq!(varied.headers()["Vary"], "Accept-Language");
+
+        query.insert("language".to_string(), "en".to_string());
+        let fixed = vary_on_accept_language(&query, response());
+        assert!(fixed.headers().get("Vary").is_none());
+    }
+
+    #[test]
+    fn exact_match_keeps_text() {
+        assert_eq!(
+            MatchMode::Exact.apply(&Language::English, "Hi-Potion"),
+            "Hi-Potion"
+        );
+    }
+}
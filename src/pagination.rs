@@ -0,0 +1,205 @@
+use crate::model::{HttpErrorType, Item};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+pub const DEFAULT_LIMIT: usize = 1000;
+pub const MAX_LIMIT: usize = 1000;
+
+/// Position of a result in search order, handed to clients as an opaque token.
+///
+/// Results are ordered by descending score when they have one, then by `sort_func`.
+/// A cursor names the last result of a page rather than an offset, so the next
+/// page starts after that result even if items were added or removed meanwhile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    score: Option<f64>,
+    item_search_category_id: u32,
+    id: u32,
+}
+
+impl Cursor {
+    pub fn of(item: &Item, score: Option<f64>) -> Cursor {
+        Cursor {
+            score,
+            item_search_category_id: item.get_item_search_category_id(),
+            id: item.id,
+        }
+    }
+
+    /// Orders cursors the same way their results are ordered.
+    pub fn compare(&self, other: &Cursor) -> Ordering {
+        let order = match (self.score, other.score) {
+            (Some(a), Some(b)) => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+            _ => Ordering::Equal,
+        };
+        order
+            .then(
+                self.item_search_category_id
+                    .cmp(&other.item_search_category_id),
+            )
+            .then(self.id.cmp(&other.id))
+    }
+
+    pub fn encode(&self) -> String {
+        let raw = match self.score {
+            None => format!("{}:{}", self.item_search_category_id, self.id),
+            Some(score) => format!("{}:{}:{}", self.item_search_category_id, self.id, score),
+        };
+        base64::encode_config(raw, base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn decode(token: &str) -> Result<Cursor, HttpErrorType> {
+        let invalid = || HttpErrorType::BadRequest(format!("cursor '{}' is invalid.", token));
+        let raw = match base64::decode_config(token, base64::URL_SAFE_NO_PAD) {
+            Err(_) => return Err(invalid()),
+            Ok(bytes) => match String::from_utf8(bytes) {
+                Err(_) => return Err(invalid()),
+                Ok(raw) => raw,
+            },
+        };
+        let parts: Vec<&str> = raw.split(':').collect();
+        let score = match parts.len() {
+            2 => None,
+            3 => match parts[2].parse::<f64>() {
+                Ok(score) if score.is_finite() => Some(score),
+                _ => return Err(invalid()),
+            },
+            _ => return Err(invalid()),
+        };
+        match (parts[0].parse::<u32>(), parts[1].parse::<u32>()) {
+            (Ok(item_search_category_id), Ok(id)) => Ok(Cursor {
+                score,
+                item_search_category_id,
+                id,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Reads `limit` from the query string, falling back to `default` and allowing up to `max`.
+pub fn parse_limit(
+    query: &HashMap<String, String>,
+    default: usize,
+    max: usize,
+) -> Result<usize, HttpErrorType> {
+    match query.get("limit") {
+        None => Ok(default),
+        Some(text) => match text.parse::<usize>() {
+            Ok(limit) if (1..=max).contains(&limit) => Ok(limit),
+            _ => Err(HttpErrorType::BadRequest(format!(
+                "limit must be a number between 1 and {}.",
+                max
+            ))),
+        },
+    }
+}
+
+#[derive(Debug)]
+pub struct Page {
+    pub limit: usize,
+    pub cursor: Option<Cursor>,
+}
+
+impl Page {
+    /// Reads `limit` and `cursor` from the query string.
+    pub fn parse(query: &HashMap<String, String>) -> Result<Page, HttpErrorType> {
+        let limit = match parse_limit(query, DEFAULT_LIMIT, MAX_LIMIT) {
+            Err(e) => return Err(e),
+            Ok(limit) => limit,
+        };
+        let cursor = match query.get("cursor") {
+            None => None,
+            Some(token) => match Cursor::decode(token) {
+                Err(e) => return Err(e),
+                Ok(cursor) => Some(cursor),
+            },
+        };
+        Ok(Page { limit, cursor })
+    }
+
+    /// Cuts one page out of `results`, which must already be in the order of the
+    /// cursors `cursor_of` returns for them.
+    ///
+    /// Returns the page and the token of the following page, if there is one.
+    pub fn apply<T, F>(&self, results: Vec<T>, cursor_of: F) -> (Vec<T>, Option<String>)
+    where
+        F: Fn(&T) -> Cursor,
+    {
+        let mut rest = results.into_iter().skip_while(|result| match &self.cursor {
+            None => false,
+            Some(cursor) => cursor_of(result).compare(cursor) != Ordering::Greater,
+        });
+        let page: Vec<T> = rest.by_ref().take(self.limit).collect();
+        let next_cursor = match (rest.next(), page.last()) {
+            (Some(_), Some(last)) => Some(cursor_of(last).encode()),
+            _ => None,
+        };
+        (page, next_cursor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor(score: Option<f64>, item_search_category_id: u32, id: u32) -> Cursor {
+        Cursor {
+            score,
+            item_search_category_id,
+            id,
+        }
+    }
+
+    #[test]
+    fn cursors_survive_encoding() {
+        for original in [cursor(None, 43, 4551), cursor(Some(0.875), 0, 1)].iter() {
+            assert_eq!(&Cursor::decode(&original.encode()).unwrap(), original);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_cursors() {
+        let encode = |raw: &str| base64::encode_config(raw, base64::URL_SAFE_NO_PAD);
+        for token in [
+            "!!".to_string(),
+            encode("43"),
+            encode("43:x"),
+            encode("43:4551:NaN"),
+            encode("43:4551:0.5:1"),
+        ]
+        .iter()
+        {
+            assert!(Cursor::decode(token).is_err(), "{} was accepted", token);
+        }
+    }
+
+    #[test]
+    fn orders_by_score_then_category_then_id() {
+        assert_eq!(
+            cursor(Some(0.9), 50, 9).compare(&cursor(Some(0.8), 1, 1)),
+            Ordering::Less
+        );
+        assert_eq!(
+            cursor(None, 1, 9).compare(&cursor(None, 2, 1)),
+            Ordering::Less
+        );
+        assert_eq!(
+            cursor(None, 2, 1).compare(&cursor(None, 2, 1)),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn rejects_limits_out_of_range() {
+        let query = |limit: &str| -> HashMap<String, String> {
+            let mut query = HashMap::new();
+            query.insert("limit".to_string(), limit.to_string());
+            query
+        };
+        assert_eq!(Page::parse(&query("1")).unwrap().limit, 1);
+        assert!(Page::parse(&query("0")).is_err());
+        assert!(Page::parse(&query("1001")).is_err());
+        assert_eq!(Page::parse(&HashMap::new()).unwrap().limit, DEFAULT_LIMIT);
+    }
+}
@@ -0,0 +1,362 @@
+use crate::model::{HttpErrorType, Item, Language};
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Top level attributes of `Item`, named as they are stored and serialized.
+pub const FIELDS: [&str; 10] = [
+    "ID",
+    "Icon",
+    "ItemSearchCategory",
+    "Name_de",
+    "Name_en",
+    "Name_fr",
+    "Name_ja",
+    "Name_zh",
+    "Name_ko",
+    "EorzeaDatabaseId",
+];
+
+/// The localized name returned in place of `Name_*` when `display_language` is given.
+const NAME_FIELD: &str = "Name";
+
+/// Codes of the languages an item has a name in. It is derived from the names, not stored.
+const AVAILABLE_LANGUAGES_FIELD: &str = "AvailableLanguages";
+
+/// Attributes every fetch needs, because results are sorted and filtered by them.
+const REQUIRED_FIELDS: [&str; 2] = ["ID", "ItemSearchCategory"];
+
+/// Item attributes a client asked for with `fields`, e.g. `fields=ID,Name_en,Icon`.
+///
+/// Without `fields` every attribute is returned. With `display_language` the
+/// `Name_*` attributes collapse into a single `Name` in that language, which `fields`
+/// can then ask for as `Name`. `AvailableLanguages` is added unless `fields` leaves it out.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Projection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fields: Option<Vec<String>>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_language"
+    )]
+    display_language: Option<Language>,
+}
+
+fn serialize_language<S: Serializer>(
+    language: &Option<Language>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match language {
+        None => serializer.serialize_none(),
+        Some(language) => serializer.serialize_str(&language.to_string()),
+    }
+}
+
+impl Projection {
+    /// Reads `fields` (comma separated attribute names) and `display_language` from the
+    /// query string.
+    pub fn parse(query: &HashMap<String, String>) -> Result<Projection, HttpErrorType> {
+        let display_language = match query.get("display_language") {
+            None => None,
+            Some(lang) => match Language::from_str(lang) {
+                Err(_) => {
+                    return Err(HttpErrorType::BadRequest(format!(
+                        "display_language '{}' is invalid.",
+                        lang
+                    )))
+                }
+                Ok(lang) => Some(lang),
+            },
+        };
+        let fields = match query.get("fields") {
+            None => None,
+            Some(text) => match parse_fields(text, display_language.is_some()) {
+                Err(e) => return Err(e),
+                Ok(fields) => Some(fields),
+            },
+        };
+        Ok(Projection {
+            fields,
+            display_language,
+        })
+    }
+
+    pub fn is_all(&self) -> bool {
+        self.fields.is_none() && self.display_language.is_none()
+    }
+
+    fn wants_available_languages(&self) -> bool {
+        match &self.fields {
+            None => true,
+            Some(fields) => fields
+                .iter()
+                .any(|field| field == AVAILABLE_LANGUAGES_FIELD),
+        }
+    }
+
+    /// Whether the response shows any name.
+    fn wants_name(&self) -> bool {
+        match &self.fields {
+            None => true,
+            Some(fields) => fields
+                .iter()
+                .any(|field| field == NAME_FIELD || field.starts_with("Name_")),
+        }
+    }
+
+    /// The DynamoDB `ProjectionExpression` and its `ExpressionAttributeNames` fetching
+    /// the requested attributes plus those needed to sort and filter, or `None` to
+    /// fetch everything.
+    pub fn expression(&self) -> Option<(String, HashMap<String, String>)> {
+        if self.is_all() {
+            return None;
+        }
+        let mut names: Vec<String> = REQUIRED_FIELDS.iter().map(|f| f.to_string()).collect();
+        let requested: Vec<String> = match &self.fields {
+            None => FIELDS.iter().map(|f| f.to_string()).collect(),
+            Some(fields) => fields.clone(),
+        };
+        for field in requested {
+            if field == NAME_FIELD || field == AVAILABLE_LANGUAGES_FIELD || names.contains(&field) {
+                continue;
+            }
+            names.push(field);
+        }
+        // `Name` may fall back to any language, and `AvailableLanguages` checks them all.
+        if (self.display_language.is_some() && self.wants_name())
+            || self.wants_available_languages()
+        {
+            for lang in Language::all() {
+                let key = lang.get_key();
+                if !names.contains(&key) {
+                    names.push(key);
+                }
+            }
+        }
+        let expression = (0..names.len())
+            .map(|i| format!("#field{}", i))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let attribute_names = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (format!("#field{}", i), name.clone()))
+            .collect();
+        Some((expression, attribute_names))
+    }
+
+    /// Serializes `item` with only the requested attributes.
+    pub fn apply(&self, item: &Item) -> Map<String, Value> {
+        let mut map = match serde_json::to_value(item) {
+            Ok(Value::Object(map)) => map,
+            _ => Map::new(),
+        };
+        let mut map: Map<String, Value> = match &self.fields {
+            None => map,
+            Some(fields) => fields
+                .iter()
+                .filter_map(|field| map.remove(field).map(|value| (field.clone(), value)))
+                .collect(),
+        };
+        match self.display_language {
+            None => (),
+            Some(lang) => {
+                for language in Language::all() {
+                    map.remove(&language.get_key());
+                }
+                if self.wants_name() {
+                    map.insert(NAME_FIELD.to_string(), Value::from(item.get_name(&lang)));
+                }
+            }
+        }
+        if self.wants_available_languages() {
+            let codes: Vec<String> = item
+                .available_languages()
+                .iter()
+                .map(|lang| lang.to_string())
+                .collect();
+            map.insert(AVAILABLE_LANGUAGES_FIELD.to_string(), Value::from(codes));
+        }
+        map
+    }
+}
+
+/// Validates comma separated attribute names, dropping repeated ones. `Name` is only
+/// accepted along with `display_language`.
+fn parse_fields(text: &str, has_display_language: bool) -> Result<Vec<String>, HttpErrorType> {
+    let mut fields: Vec<String> = Vec::new();
+    for field in text.split(',') {
+        let known = FIELDS.contains(&field)
+            || field == AVAILABLE_LANGUAGES_FIELD
+            || (has_display_language && field == NAME_FIELD);
+        if !known {
+            return Err(HttpErrorType::BadRequest(format!(
+                "field '{}' is invalid. fields must be some of {},{}.",
+                field,
+                FIELDS.join(","),
+                AVAILABLE_LANGUAGES_FIELD
+            )));
+        }
+        if !fields.iter().any(|f| f == field) {
+            fields.push(field.to_string());
+        }
+    }
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn projection(pairs: &[(&str, &str)]) -> Projection {
+        Projection::parse(&query(pairs)).unwrap()
+    }
+
+    fn fixture(id: u32) -> Item {
+        let items: Vec<Item> =
+            serde_json::from_str(include_str!("../tests/fixtures/items.json")).unwrap();
+        items.into_iter().find(|item| item.id == id).unwrap()
+    }
+
+    fn keys(map: &Map<String, Value>) -> Vec<&str> {
+        let mut keys: Vec<&str> = map.keys().map(|key| key.as_str()).collect();
+        keys.sort();
+        keys
+    }
+
+    /// The attributes `expression` fetches, in the order of its placeholders.
+    fn fetched(projection: &Projection) -> Vec<String> {
+        let (expression, names) = projection.expression().unwrap();
+        let placeholders: Vec<&str> = expression.split(", ").collect();
+        assert_eq!(placeholders.len(), names.len());
+        placeholders
+            .iter()
+            .enumerate()
+            .map(|(i, placeholder)| {
+                assert_eq!(*placeholder, format!("#field{}", i));
+                names[*placeholder].clone()
+            })
+            .collect()
+    }
+
+    fn name_keys() -> Vec<String> {
+        Language::all().iter().map(|lang| lang.get_key()).collect()
+    }
+
+    #[test]
+    fn fetches_everything_without_a_projection() {
+        assert!(projection(&[]).is_all());
+        assert!(projection(&[]).expression().is_none());
+    }
+
+    #[test]
+    fn always_fetches_id_and_category() {
+        assert_eq!(
+            fetched(&projection(&[("fields", "Icon")])),
+            vec!["ID", "ItemSearchCategory", "Icon"]
+        );
+        assert_eq!(
+            fetched(&projection(&[(
+                "fields",
+                "ItemSearchCategory,Icon,ID,Icon"
+            )])),
+            vec!["ID", "ItemSearchCategory", "Icon"]
+        );
+        assert_eq!(
+            fetched(&projection(&[("fields", "ID"), ("display_language", "en")])),
+            vec!["ID", "ItemSearchCategory"]
+        );
+    }
+
+    #[test]
+    fn fetches_every_name_when_languages_are_derived() {
+        let mut expected: Vec<String> = vec!["ID", "ItemSearchCategory", "Icon"]
+            .into_iter()
+            .map(|field| field.to_string())
+            .collect();
+        expected.extend(name_keys());
+        assert_eq!(
+            fetched(&projection(&[("fields", "Icon,AvailableLanguages")])),
+            expected
+        );
+
+        let expected = vec![
+            "ID",
+            "ItemSearchCategory",
+            "Name_ja",
+            "Name_de",
+            "Name_fr",
+            "Name_en",
+            "Name_zh",
+            "Name_ko",
+        ];
+        assert_eq!(
+            fetched(&projection(&[
+                ("fields", "Name_ja,Name"),
+                ("display_language", "ja")
+            ])),
+            expected
+        );
+
+        let all = fetched(&projection(&[("display_language", "en")]));
+        assert_eq!(all.len(), FIELDS.len());
+        for field in FIELDS.iter() {
+            assert!(all.contains(&field.to_string()), "{} is not fetched", field);
+        }
+    }
+
+    #[test]
+    fn collapses_names_into_name() {
+        let map = projection(&[("display_language", "fr")]).apply(&fixture(4551));
+        assert_eq!(map["Name"], "Potion");
+        assert!(!map.keys().any(|key| key.starts_with("Name_")));
+        assert_eq!(map["ID"], 4551);
+        assert_eq!(map["AvailableLanguages"].as_array().unwrap().len(), 6);
+
+        let map =
+            projection(&[("fields", "ID,Name"), ("display_language", "ja")]).apply(&fixture(4551));
+        assert_eq!(keys(&map), vec!["ID", "Name"]);
+        assert_eq!(map["Name"], "ポーション");
+
+        let map = projection(&[("fields", "ID,Name_de"), ("display_language", "ja")])
+            .apply(&fixture(4551));
+        assert_eq!(keys(&map), vec!["ID", "Name"]);
+    }
+
+    #[test]
+    fn falls_back_to_english_then_japanese_then_any_name() {
+        let display = |item: &Item, lang: &str| {
+            projection(&[("display_language", lang)]).apply(item)["Name"].clone()
+        };
+        let mut item = fixture(12345);
+        assert_eq!(display(&item, "de"), "Eau de Vie");
+        assert_eq!(display(&item, "ja"), "オー・ド・ヴィー");
+
+        item.name_en = None;
+        assert_eq!(display(&item, "de"), "オー・ド・ヴィー");
+
+        item.name_ja = None;
+        item.name_ko = Some("오드비".to_string());
+        assert_eq!(display(&item, "de"), "오드비");
+
+        item.name_ko = None;
+        assert_eq!(display(&item, "de"), "");
+    }
+
+    #[test]
+    fn accepts_name_only_with_display_language() {
+        let parse = |pairs: &[(&str, &str)]| Projection::parse(&query(pairs));
+        assert!(parse(&[("fields", "ID,Name")]).is_err());
+        assert!(parse(&[("fields", "ID,Name"), ("display_language", "xx")]).is_err());
+        assert!(parse(&[("fields", "ID,Name"), ("display_language", "en")]).is_ok());
+        assert!(parse(&[("fields", "ID,Price")]).is_err());
+    }
+}
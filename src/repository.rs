@@ -0,0 +1,138 @@
+mod dynamodb;
+mod memory;
+
+pub use dynamodb::DynamoDbItemRepository;
+pub use memory::InMemoryItemRepository;
+
+use crate::fuzzy;
+use crate::model::{HttpErrorType, Item, Language, MatchMode};
+use crate::projection::Projection;
+use async_trait::async_trait;
+use log::info;
+use once_cell::sync::OnceCell;
+use std::env;
+use std::time::Instant;
+
+/// An item found by a name search, with the languages whose name matched.
+///
+/// `score` is only set by fuzzy searches, see `fuzzy::score`.
+#[derive(Debug, Clone)]
+pub struct NameMatch {
+    pub item: Item,
+    pub languages: Vec<Language>,
+    pub score: Option<f64>,
+}
+
+/// Storage backend the handlers read items from.
+#[async_trait]
+pub trait ItemRepository: Send + Sync {
+    /// Returns every item whose name in any of `langs` contains `string` once both are
+    /// prepared by `mode`, in no particular order.
+    async fn search_by_name(
+        &self,
+        langs: &[Language],
+        string: &str,
+        mode: &MatchMode,
+    ) -> Result<Vec<NameMatch>, HttpErrorType>;
+
+    /// Returns every item whose name in any of `langs` resembles `string` closely enough,
+    /// with its best score, in no particular order.
+    async fn fuzzy_search_by_name(
+        &self,
+        langs: &[Language],
+        string: &str,
+        mode: &MatchMode,
+    ) -> Result<Vec<NameMatch>, HttpErrorType> {
+        let items = match self.scan_all().await {
+            Err(e) => return Err(e),
+            Ok(items) => items,
+        };
+        Ok(items
+            .into_iter()
+            .filter_map(|item| fuzzy::match_item(item, langs, string, mode))
+            .collect())
+    }
+
+    /// Returns the items with the given IDs. IDs without an item are skipped.
+    async fn batch_get_by_ids(&self, ids: &[u32]) -> Result<Vec<Item>, HttpErrorType>;
+
+    /// Same as `batch_get_by_ids`, but backends may fetch only the attributes
+    /// `projection` needs. Attributes left out are empty in the returned items.
+    async fn batch_get_projected(
+        &self,
+        ids: &[u32],
+        _projection: &Projection,
+    ) -> Result<Vec<Item>, HttpErrorType> {
+        self.batch_get_by_ids(ids).await
+    }
+
+    /// Returns every item in the backend, in no particular order.
+    async fn scan_all(&self) -> Result<Vec<Item>, HttpErrorType>;
+
+    /// Returns the item with the given ID, or `None` when it does not exist.
+    ///
+    /// A malformed item is always an error here, whatever the decode policy.
+    async fn get_one(&self, id: u32) -> Result<Option<Item>, HttpErrorType>;
+
+    /// How many malformed items have been skipped so far instead of failing the request.
+    fn warnings(&self) -> usize {
+        0
+    }
+
+    /// IDs of the malformed items skipped so far, for those whose ID could still be read.
+    fn skipped_ids(&self) -> Vec<u32> {
+        Vec::new()
+    }
+}
+
+static LOADED_REPOSITORY: OnceCell<InMemoryItemRepository> = OnceCell::new();
+
+/// Reads every item from the JSON snapshot named by `ITEM_SNAPSHOT_PATH` when it is set,
+/// otherwise from a full Scan of the `TABLE_NAME` table.
+///
+/// Returns the items with the number of malformed items that were skipped. Retries of the
+/// Scan give up at `deadline`.
+pub async fn load_items(deadline: Instant) -> Result<(Vec<Item>, usize), HttpErrorType> {
+    let (items, warnings) = match env::var("ITEM_SNAPSHOT_PATH") {
+        Ok(path) => {
+            info!("load items from snapshot: {}", path);
+            match InMemoryItemRepository::read_snapshot(&path) {
+                Err(e) => return Err(e),
+                Ok(items) => (items, 0),
+            }
+        }
+        Err(_) => {
+            let dynamodb = match DynamoDbItemRepository::from_env() {
+                Err(e) => return Err(e),
+                Ok(dynamodb) => dynamodb.with_deadline(deadline),
+            };
+            info!("load items from table: {}", dynamodb.table_name());
+            match dynamodb.scan_all().await {
+                Err(e) => return Err(e),
+                Ok(items) => (items, dynamodb.warnings()),
+            }
+        }
+    };
+    info!("loaded {} items, skipped {}", items.len(), warnings);
+    Ok((items, warnings))
+}
+
+/// Returns an in-memory copy of every item, loading it with `load_items` on the first
+/// call in this container. A failed load is not cached, so the next invocation tries again.
+pub async fn load_in_memory_repository(
+    deadline: Instant,
+) -> Result<&'static InMemoryItemRepository, HttpErrorType> {
+    if let Some(repository) = LOADED_REPOSITORY.get() {
+        return Ok(repository);
+    }
+
+    let (items, warnings) = match load_items(deadline).await {
+        Err(e) => return Err(e),
+        Ok(loaded) => loaded,
+    };
+
+    Ok(
+        LOADED_REPOSITORY
+            .get_or_init(|| InMemoryItemRepository::new(items).with_warnings(warnings)),
+    )
+}
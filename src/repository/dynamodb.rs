@@ -0,0 +1,454 @@
+use super::{ItemRepository, NameMatch};
+use crate::model::{
+    convert_dynamodb_item_to_item, get_table_name, DecodePolicy, HttpErrorType, Item, Language,
+    MatchMode,
+};
+use crate::projection::Projection;
+use crate::retry::{retry, RetryPolicy};
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use log::warn;
+use maplit::hashmap;
+use rusoto_core::Region;
+use rusoto_dynamodb::{
+    AttributeValue, BatchGetItemInput, DynamoDb, DynamoDbClient, GetItemInput, KeysAndAttributes,
+    ScanInput,
+};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Used when `BATCH_GET_CONCURRENCY` is not set.
+const DEFAULT_BATCH_GET_CONCURRENCY: usize = 4;
+
+pub struct DynamoDbItemRepository {
+    client: DynamoDbClient,
+    table_name: String,
+    retry_policy: RetryPolicy,
+    batch_get_concurrency: usize,
+    decode_policy: DecodePolicy,
+    skipped: AtomicUsize,
+    skipped_ids: Mutex<Vec<u32>>,
+    created: Instant,
+    deadline: Option<Instant>,
+}
+
+impl DynamoDbItemRepository {
+    pub fn new(client: DynamoDbClient, table_name: String) -> DynamoDbItemRepository {
+        DynamoDbItemRepository {
+            client,
+            table_name,
+            retry_policy: RetryPolicy::default(),
+            batch_get_concurrency: DEFAULT_BATCH_GET_CONCURRENCY,
+            decode_policy: DecodePolicy::Lenient,
+            skipped: AtomicUsize::new(0),
+            skipped_ids: Mutex::new(Vec::new()),
+            created: Instant::now(),
+            deadline: None,
+        }
+    }
+
+    /// Gives up retrying at `deadline`, usually the end of the invocation, instead of
+    /// `RetryPolicy::deadline` after the repository was built.
+    pub fn with_deadline(self, deadline: Instant) -> DynamoDbItemRepository {
+        DynamoDbItemRepository {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+
+    pub fn with_decode_policy(self, decode_policy: DecodePolicy) -> DynamoDbItemRepository {
+        DynamoDbItemRepository {
+            decode_policy,
+            ..self
+        }
+    }
+
+    pub fn with_retry_policy(self, retry_policy: RetryPolicy) -> DynamoDbItemRepository {
+        DynamoDbItemRepository {
+            retry_policy,
+            ..self
+        }
+    }
+
+    /// Caps how many BatchGetItem requests `batch_get_by_ids` keeps in flight.
+    pub fn with_batch_get_concurrency(
+        self,
+        batch_get_concurrency: usize,
+    ) -> DynamoDbItemRepository {
+        DynamoDbItemRepository {
+            batch_get_concurrency,
+            ..self
+        }
+    }
+
+    /// Builds a repository for the table named by the `TABLE_NAME` environment variable.
+    ///
+    /// `BATCH_GET_CONCURRENCY` overrides the default concurrency of `batch_get_by_ids`,
+    /// and `DECODE_POLICY` (`strict` or `lenient`) the default `DecodePolicy::Lenient`.
+    pub fn from_env() -> Result<DynamoDbItemRepository, HttpErrorType> {
+        let table_name = match get_table_name() {
+            Err(e) => return Err(e),
+            Ok(name) => name,
+        };
+        let mut repository =
+            DynamoDbItemRepository::new(DynamoDbClient::new(Region::default()), table_name);
+        repository = match env::var("BATCH_GET_CONCURRENCY") {
+            Err(_) => repository,
+            Ok(text) => match text.parse::<usize>() {
+                Ok(concurrency) if concurrency > 0 => {
+                    repository.with_batch_get_concurrency(concurrency)
+                }
+                _ => {
+                    return Err(HttpErrorType::InternalServerError(format!(
+                        "Environment Value BATCH_GET_CONCURRENCY '{}' is invalid.",
+                        text
+                    )))
+                }
+            },
+        };
+        repository = match env::var("DECODE_POLICY") {
+            Err(_) => repository,
+            Ok(text) => match DecodePolicy::from_str(&text) {
+                Ok(policy) => repository.with_decode_policy(policy),
+                Err(_) => {
+                    return Err(HttpErrorType::InternalServerError(format!(
+                        "Environment Value DECODE_POLICY '{}' is invalid.",
+                        text
+                    )))
+                }
+            },
+        };
+        Ok(repository)
+    }
+
+    /// Converts one stored item, or under `DecodePolicy::Lenient` skips it when it is
+    /// malformed.
+    fn decode(
+        &self,
+        item: &HashMap<String, AttributeValue>,
+    ) -> Result<Option<Item>, HttpErrorType> {
+        match convert_dynamodb_item_to_item(item) {
+            Ok(item) => Ok(Some(item)),
+            Err(e) => match self.decode_policy {
+                DecodePolicy::Strict => Err(e),
+                DecodePolicy::Lenient => {
+                    warn!("skipped malformed item: {}", e.message());
+                    self.skipped.fetch_add(1, Ordering::Relaxed);
+                    if let (Some(id), Ok(mut ids)) = (stored_id(item), self.skipped_ids.lock()) {
+                        ids.push(id);
+                    }
+                    Ok(None)
+                }
+            },
+        }
+    }
+
+    pub fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    /// The instant every retry of this repository gives up by.
+    fn deadline(&self) -> Instant {
+        match self.deadline {
+            Some(deadline) => deadline,
+            None => self.created + self.retry_policy.deadline,
+        }
+    }
+
+    /// Runs `input` as a paginated Scan and converts every returned item.
+    ///
+    /// Each page gets its own attempts, but all of them share the repository's deadline.
+    async fn scan(&self, input: ScanInput) -> Result<Vec<Item>, HttpErrorType> {
+        let mut result: Vec<Item> = Vec::new();
+        let mut last_evaluated_key: Option<HashMap<String, AttributeValue>> = None;
+
+        while {
+            let input = ScanInput {
+                exclusive_start_key: last_evaluated_key.clone(),
+                ..input.clone()
+            };
+
+            let mut backoff = self.retry_policy.start(self.deadline());
+            let resp = match retry(&mut backoff, "scan", || self.client.scan(input.clone())).await {
+                Err(e) => return Err(e),
+                Ok(resp) => resp,
+            };
+
+            last_evaluated_key = resp.last_evaluated_key;
+
+            let items: Vec<HashMap<String, AttributeValue>> = match resp.items {
+                None => Vec::new(),
+                Some(items) => items,
+            };
+
+            for item in items {
+                match self.decode(&item) {
+                    Err(e) => return Err(e),
+                    Ok(None) => (),
+                    Ok(Some(item)) => result.push(item),
+                }
+            }
+
+            last_evaluated_key.is_some()
+        } {}
+
+        Ok(result)
+    }
+
+    /// Fetches up to 100 items with BatchGetItem, re-submitting unprocessed keys.
+    async fn batch_get_chunk(
+        &self,
+        chunk: &[u32],
+        projection: &Projection,
+    ) -> Result<Vec<Item>, HttpErrorType> {
+        let mut result: Vec<Item> = Vec::new();
+        let (projection_expression, expression_attribute_names) = match projection.expression() {
+            None => (None, None),
+            Some((expression, names)) => (Some(expression), Some(names)),
+        };
+
+        // Unprocessed keys mean the table is throttling us, so they are re-submitted
+        // with the same backoff as a failed request.
+        let mut backoff = self.retry_policy.start(self.deadline());
+        let mut keys_and_attributes: Option<KeysAndAttributes> = Some(KeysAndAttributes {
+            keys: chunk.iter().map(|id| create_key(*id)).collect(),
+            projection_expression,
+            expression_attribute_names,
+            ..Default::default()
+        });
+        while keys_and_attributes.is_some() {
+            let current: KeysAndAttributes = keys_and_attributes.unwrap();
+
+            let mut request_items: HashMap<String, KeysAndAttributes> = HashMap::new();
+            request_items.insert(self.table_name.clone(), current);
+            let input = BatchGetItemInput {
+                request_items,
+                ..Default::default()
+            };
+
+            let resp = match retry(&mut backoff, "batch get item", || {
+                self.client.batch_get_item(input.clone())
+            })
+            .await
+            {
+                Err(e) => return Err(e),
+                Ok(resp) => resp,
+            };
+            match resp.responses {
+                None => (),
+                Some(table_response) => match table_response.get(&self.table_name) {
+                    None => (),
+                    Some(items) => {
+                        if !items.is_empty() {
+                            backoff.reset();
+                        }
+                        for item in items {
+                            let decoded = if projection.is_all() {
+                                self.decode(item)
+                            } else {
+                                self.decode(&fill_projected_out(item))
+                            };
+                            match decoded {
+                                Err(e) => return Err(e),
+                                Ok(None) => (),
+                                Ok(Some(item)) => result.push(item),
+                            }
+                        }
+                    }
+                },
+            };
+
+            keys_and_attributes = match resp.unprocessed_keys {
+                None => None,
+                Some(unprocessed) => unprocessed.get(&self.table_name).cloned(),
+            };
+            if keys_and_attributes.is_some() {
+                match backoff.wait("batch get item").await {
+                    Err(give_up) => {
+                        warn!("{}", give_up.message());
+                        return Err(HttpErrorType::throttled());
+                    }
+                    Ok(_) => (),
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+/// Gives `Icon` an empty value when a projection left it out, so the item still converts.
+///
+/// It is the only attribute `Item` requires besides `ID` and `ItemSearchCategory`,
+/// which every projection fetches.
+fn fill_projected_out(item: &HashMap<String, AttributeValue>) -> HashMap<String, AttributeValue> {
+    let mut item = item.clone();
+    if !item.contains_key("Icon") {
+        item.insert(
+            "Icon".to_string(),
+            AttributeValue {
+                s: Some(String::new()),
+                ..Default::default()
+            },
+        );
+    }
+    item
+}
+
+/// The `ID` of a stored item, read without converting the rest of it.
+fn stored_id(item: &HashMap<String, AttributeValue>) -> Option<u32> {
+    item.get("ID")
+        .and_then(|id| id.n.as_ref())
+        .and_then(|id| id.parse::<u32>().ok())
+}
+
+fn create_key(id: u32) -> HashMap<String, AttributeValue> {
+    hashmap! {
+        "ID".to_string() => AttributeValue {
+            n: Some(id.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+#[async_trait]
+impl ItemRepository for DynamoDbItemRepository {
+    async fn search_by_name(
+        &self,
+        langs: &[Language],
+        string: &str,
+        mode: &MatchMode,
+    ) -> Result<Vec<NameMatch>, HttpErrorType> {
+        // DynamoDB can only compare names as stored, so normalized matching
+        // has to fetch every item and compare them here.
+        let input = match mode {
+            MatchMode::Exact => ScanInput {
+                table_name: self.table_name.clone(),
+                filter_expression: Some(
+                    (0..langs.len())
+                        .map(|i| format!("contains(#path{}, :value)", i))
+                        .collect::<Vec<String>>()
+                        .join(" OR "),
+                ),
+                expression_attribute_names: Some(
+                    langs
+                        .iter()
+                        .enumerate()
+                        .map(|(i, lang)| (format!("#path{}", i), lang.get_key()))
+                        .collect(),
+                ),
+                expression_attribute_values: Some(hashmap! {
+                    ":value".to_string() => AttributeValue {
+                        s: Some(string.to_string()),
+                        ..Default::default()
+                    }
+                }),
+                ..Default::default()
+            },
+            MatchMode::Normalized => ScanInput {
+                table_name: self.table_name.clone(),
+                ..Default::default()
+            },
+        };
+        let items = match self.scan(input).await {
+            Err(e) => return Err(e),
+            Ok(items) => items,
+        };
+
+        Ok(items
+            .into_iter()
+            .filter_map(|item| {
+                let languages: Vec<Language> = langs
+                    .iter()
+                    .filter(|lang| match item.name(lang) {
+                        None => false,
+                        Some(name) => mode.apply(lang, name).contains(&mode.apply(lang, string)),
+                    })
+                    .cloned()
+                    .collect();
+                if languages.is_empty() {
+                    None
+                } else {
+                    Some(NameMatch {
+                        item,
+                        languages,
+                        score: None,
+                    })
+                }
+            })
+            .collect())
+    }
+
+    async fn batch_get_by_ids(&self, ids: &[u32]) -> Result<Vec<Item>, HttpErrorType> {
+        self.batch_get_projected(ids, &Projection::default()).await
+    }
+
+    async fn batch_get_projected(
+        &self,
+        ids: &[u32],
+        projection: &Projection,
+    ) -> Result<Vec<Item>, HttpErrorType> {
+        // BatchGetItem rejects a request that names the same key twice.
+        let mut seen: HashSet<u32> = HashSet::new();
+        let ids: Vec<u32> = ids.iter().filter(|id| seen.insert(**id)).cloned().collect();
+
+        // Chunks are fetched concurrently, so items come back in no particular order.
+        let requests: Vec<_> = ids
+            .chunks(100)
+            .map(|chunk| self.batch_get_chunk(chunk, projection))
+            .collect();
+        stream::iter(requests)
+            .buffer_unordered(self.batch_get_concurrency)
+            .try_concat()
+            .await
+    }
+
+    fn warnings(&self) -> usize {
+        self.skipped.load(Ordering::Relaxed)
+    }
+
+    fn skipped_ids(&self) -> Vec<u32> {
+        match self.skipped_ids.lock() {
+            Err(_) => Vec::new(),
+            Ok(ids) => ids.clone(),
+        }
+    }
+
+    async fn scan_all(&self) -> Result<Vec<Item>, HttpErrorType> {
+        self.scan(ScanInput {
+            table_name: self.table_name.clone(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    async fn get_one(&self, id: u32) -> Result<Option<Item>, HttpErrorType> {
+        let input = GetItemInput {
+            table_name: self.table_name.clone(),
+            key: create_key(id),
+            ..Default::default()
+        };
+
+        let mut backoff = self.retry_policy.start(self.deadline());
+        let resp = match retry(&mut backoff, "get item", || {
+            self.client.get_item(input.clone())
+        })
+        .await
+        {
+            Err(e) => return Err(e),
+            Ok(resp) => resp,
+        };
+
+        match resp.item {
+            None => Ok(None),
+            Some(item) => match convert_dynamodb_item_to_item(&item) {
+                Err(e) => Err(e),
+                Ok(item) => Ok(Some(item)),
+            },
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use super::{ItemRepository, NameMatch};
+use crate::index::SearchIndex;
+use crate::model::{sort_func, HttpErrorType, Item, Language, MatchMode};
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+/// Repository backed by items held in memory, e.g. loaded from a JSON fixture.
+///
+/// Items are kept in `sort_func` order and name searches are answered from a
+/// `SearchIndex`, so building one is meant to happen once per container.
+pub struct InMemoryItemRepository {
+    items: Vec<Item>,
+    positions: HashMap<u32, usize>,
+    index: SearchIndex,
+    warnings: usize,
+}
+
+impl InMemoryItemRepository {
+    pub fn new(items: Vec<Item>) -> InMemoryItemRepository {
+        let unique: HashMap<u32, Item> = items.into_iter().map(|item| (item.id, item)).collect();
+        let mut items: Vec<Item> = unique.values().cloned().collect();
+        items.sort_by(sort_func);
+
+        let positions = items
+            .iter()
+            .enumerate()
+            .map(|(position, item)| (item.id, position))
+            .collect();
+        let index = SearchIndex::build(&items);
+
+        InMemoryItemRepository {
+            items,
+            positions,
+            index,
+            warnings: 0,
+        }
+    }
+
+    /// Records how many malformed items were skipped while loading `items`.
+    pub fn with_warnings(self, warnings: usize) -> InMemoryItemRepository {
+        InMemoryItemRepository { warnings, ..self }
+    }
+
+    /// Parses a JSON array of items in the same shape the API returns them.
+    pub fn parse_snapshot(json: &str) -> Result<Vec<Item>, HttpErrorType> {
+        match serde_json::from_str::<Vec<Item>>(json) {
+            Err(e) => Err(HttpErrorType::InternalServerError(format!(
+                "failed to parse items json: {}",
+                e
+            ))),
+            Ok(items) => Ok(items),
+        }
+    }
+
+    pub fn read_snapshot(path: &str) -> Result<Vec<Item>, HttpErrorType> {
+        match fs::read_to_string(path) {
+            Err(e) => Err(HttpErrorType::InternalServerError(format!(
+                "failed to read '{}': {}",
+                path, e
+            ))),
+            Ok(json) => InMemoryItemRepository::parse_snapshot(&json),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<InMemoryItemRepository, HttpErrorType> {
+        match InMemoryItemRepository::parse_snapshot(json) {
+            Err(e) => Err(e),
+            Ok(items) => Ok(InMemoryItemRepository::new(items)),
+        }
+    }
+
+    pub fn from_file(path: &str) -> Result<InMemoryItemRepository, HttpErrorType> {
+        match InMemoryItemRepository::read_snapshot(path) {
+            Err(e) => Err(e),
+            Ok(items) => Ok(InMemoryItemRepository::new(items)),
+        }
+    }
+
+    /// Returns up to `limit` items whose name in `lang` starts with `prefix`, ordered by name.
+    pub fn suggest(
+        &self,
+        lang: &Language,
+        prefix: &str,
+        mode: &MatchMode,
+        limit: usize,
+    ) -> Vec<&Item> {
+        self.index
+            .prefix_search(lang, prefix, mode, limit)
+            .into_iter()
+            .map(|position| &self.items[position])
+            .collect()
+    }
+
+    fn get(&self, id: &u32) -> Option<&Item> {
+        self.positions
+            .get(id)
+            .map(|position| &self.items[*position])
+    }
+}
+
+#[async_trait]
+impl ItemRepository for InMemoryItemRepository {
+    async fn search_by_name(
+        &self,
+        langs: &[Language],
+        string: &str,
+        mode: &MatchMode,
+    ) -> Result<Vec<NameMatch>, HttpErrorType> {
+        let mut matched: BTreeMap<usize, Vec<Language>> = BTreeMap::new();
+        for lang in langs {
+            for position in self.index.search(lang, string, mode) {
+                matched.entry(position).or_default().push(*lang);
+            }
+        }
+        Ok(matched
+            .into_iter()
+            .map(|(position, languages)| NameMatch {
+                item: self.items[position].clone(),
+                languages,
+                score: None,
+            })
+            .collect())
+    }
+
+    async fn fuzzy_search_by_name(
+        &self,
+        langs: &[Language],
+        string: &str,
+        mode: &MatchMode,
+    ) -> Result<Vec<NameMatch>, HttpErrorType> {
+        let mut matched: BTreeMap<usize, (Vec<Language>, f64)> = BTreeMap::new();
+        for lang in langs {
+            for (position, score) in self.index.fuzzy_search(lang, string, mode) {
+                let entry = matched.entry(position).or_insert_with(|| (Vec::new(), 0.0));
+                entry.0.push(*lang);
+                entry.1 = entry.1.max(score);
+            }
+        }
+        Ok(matched
+            .into_iter()
+            .map(|(position, (languages, score))| NameMatch {
+                item: self.items[position].clone(),
+                languages,
+                score: Some(score),
+            })
+            .collect())
+    }
+
+    async fn batch_get_by_ids(&self, ids: &[u32]) -> Result<Vec<Item>, HttpErrorType> {
+        Ok(ids.iter().filter_map(|id| self.get(id)).cloned().collect())
+    }
+
+    async fn scan_all(&self) -> Result<Vec<Item>, HttpErrorType> {
+        Ok(self.items.clone())
+    }
+
+    async fn get_one(&self, id: u32) -> Result<Option<Item>, HttpErrorType> {
+        Ok(self.get(&id).cloned())
+    }
+
+    fn warnings(&self) -> usize {
+        self.warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ITEMS: &str = r#"[
+        {"ID": 3, "Icon": "", "ItemSearchCategory": {"ID": 1}, "Name_en": "Ether"},
+        {"ID": 2, "Icon": "", "ItemSearchCategory": {"ID": 2}, "Name_en": "Potion"},
+        {"ID": 1, "Icon": "", "ItemSearchCategory": {"ID": 2}, "Name_en": "Hi-Potion"},
+        {"ID": 1, "Icon": "", "ItemSearchCategory": {"ID": 2}, "Name_en": "Hi-Potion"}
+    ]"#;
+
+    #[test]
+    fn keeps_items_unique_and_sorted() {
+        let repository = InMemoryItemRepository::from_json(ITEMS).unwrap();
+        let ids: Vec<u32> = repository.items.iter().map(|item| item.id).collect();
+        assert_eq!(ids, vec![3, 1, 2]);
+        assert_eq!(
+            repository.get(&2).unwrap().get_name(&Language::English),
+            "Potion"
+        );
+        assert!(repository.get(&4).is_none());
+    }
+
+    #[test]
+    fn suggests_names_by_prefix() {
+        let repository = InMemoryItemRepository::from_json(ITEMS).unwrap();
+        let ids: Vec<u32> = repository
+            .suggest(&Language::English, "hi", &MatchMode::Normalized, 10)
+            .iter()
+            .map(|item| item.id)
+            .collect();
+        assert_eq!(ids, vec![1]);
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        assert!(InMemoryItemRepository::from_json("[{\"ID\": 1}]").is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use crate::model::HttpErrorType;
+use log::warn;
+use rand::Rng;
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{BatchGetItemError, GetItemError, ScanError};
+use std::error::Error;
+use std::future::Future;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::time::delay_for;
+
+/// How DynamoDB calls are retried when they are throttled or fail on the AWS side.
+///
+/// Waits grow exponentially from `base_delay` up to `max_delay` with full jitter, so
+/// concurrent invocations do not retry in lockstep. A call gives up after
+/// `max_attempts` attempts or once waiting again would pass the deadline its `Backoff`
+/// was started with, whichever comes first, leaving the Lambda time to answer before
+/// it is killed.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// How long calls may keep retrying when the end of the invocation is not known.
+    pub deadline: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 8,
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(2),
+            deadline: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Starts retrying an operation that must give up by `deadline`, which every
+    /// operation of one invocation shares.
+    pub fn start(&self, deadline: Instant) -> Backoff<'_> {
+        Backoff {
+            policy: self,
+            attempts: 1,
+            started: Instant::now(),
+            deadline,
+        }
+    }
+
+    /// Picks a random wait before attempt number `attempts + 1`.
+    fn delay(&self, attempts: u32) -> Duration {
+        let exponent = std::cmp::min(attempts - 1, 16);
+        let ceiling = std::cmp::min(self.base_delay * 2u32.pow(exponent), self.max_delay);
+        let millis = rand::thread_rng().gen_range(0, ceiling.as_millis() as u64 + 1);
+        Duration::from_millis(millis)
+    }
+}
+
+/// Attempts made so far on behalf of one operation.
+pub struct Backoff<'a> {
+    policy: &'a RetryPolicy,
+    attempts: u32,
+    started: Instant,
+    deadline: Instant,
+}
+
+impl<'a> Backoff<'a> {
+    /// Waits before the next attempt, or gives up with `ServiceUnavailable`.
+    pub async fn wait(&mut self, operation: &str) -> Result<(), HttpErrorType> {
+        let delay = self.policy.delay(self.attempts);
+        if self.attempts >= self.policy.max_attempts || Instant::now() + delay > self.deadline {
+            return Err(HttpErrorType::ServiceUnavailable(format!(
+                "gave up {} after {} attempts in {:?}",
+                operation,
+                self.attempts,
+                self.started.elapsed()
+            )));
+        }
+        delay_for(delay).await;
+        self.attempts += 1;
+        Ok(())
+    }
+
+    /// Forgets the attempts made so far while keeping the deadline, for operations
+    /// like BatchGetItem that can make progress between retries.
+    pub fn reset(&mut self) {
+        self.attempts = 1;
+    }
+}
+
+/// Time kept back from the end of the invocation to build the response.
+const RESPONSE_MARGIN: Duration = Duration::from_secs(1);
+
+/// Converts `Context::deadline`, in milliseconds since the epoch, into the instant
+/// retries of the invocation have to give up by.
+pub fn invocation_deadline(deadline_millis: u64) -> Instant {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let remaining = Duration::from_millis(deadline_millis)
+        .checked_sub(now)
+        .and_then(|remaining| remaining.checked_sub(RESPONSE_MARGIN))
+        .unwrap_or_default();
+    Instant::now() + remaining
+}
+
+/// Errors worth retrying because DynamoDB may accept the same request later.
+pub trait Retryable {
+    fn is_retryable(&self) -> bool;
+}
+
+impl<E: Retryable> Retryable for RusotoError<E> {
+    fn is_retryable(&self) -> bool {
+        match self {
+            RusotoError::Service(e) => e.is_retryable(),
+            RusotoError::HttpDispatch(_) => true,
+            RusotoError::Unknown(response) => {
+                response.status.is_server_error() || response.status.as_u16() == 429
+            }
+            _ => false,
+        }
+    }
+}
+
+macro_rules! retryable_service_error {
+    ($name:ident) => {
+        impl Retryable for $name {
+            fn is_retryable(&self) -> bool {
+                matches!(
+                    self,
+                    $name::InternalServerError(_)
+                        | $name::ProvisionedThroughputExceeded(_)
+                        | $name::RequestLimitExceeded(_)
+                )
+            }
+        }
+    };
+}
+
+retryable_service_error!(ScanError);
+retryable_service_error!(BatchGetItemError);
+retryable_service_error!(GetItemError);
+
+/// Runs `call` until it succeeds, fails with an error that is not `Retryable`, or
+/// `backoff` gives up.
+///
+/// Errors are converted into `HttpErrorType`, including the last one when `backoff` gives
+/// up, so throttling that outlasts the retries is answered with `TooManyRequests`.
+pub async fn retry<T, E, F, Fut>(
+    backoff: &mut Backoff<'_>,
+    operation: &str,
+    mut call: F,
+) -> Result<T, HttpErrorType>
+where
+    E: Retryable + Error + 'static,
+    HttpErrorType: From<RusotoError<E>>,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, RusotoError<E>>>,
+{
+    loop {
+        match call().await {
+            Ok(resp) => return Ok(resp),
+            Err(e) if e.is_retryable() => {
+                warn!("retrying {}: {}", operation, e);
+                match backoff.wait(operation).await {
+                    Err(give_up) => {
+                        warn!("{}", give_up.message());
+                        return Err(HttpErrorType::from(e));
+                    }
+                    Ok(_) => (),
+                }
+            }
+            Err(e) => {
+                warn!("error occurred in {}: {}", operation, e);
+                return Err(HttpErrorType::from(e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invocation_deadline_keeps_a_margin() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let deadline = invocation_deadline(now + 30_000);
+        assert!(deadline > Instant::now() + Duration::from_secs(28));
+        assert!(deadline <= Instant::now() + Duration::from_secs(29));
+        assert!(invocation_deadline(now - 1000) <= Instant::now());
+    }
+
+    #[tokio::test]
+    async fn gives_up_at_the_deadline() {
+        let policy = RetryPolicy::default();
+        let mut backoff = policy.start(Instant::now());
+        assert!(backoff.wait("scan").await.is_err());
+    }
+
+    async fn give_up_on(error: fn(String) -> ScanError) -> HttpErrorType {
+        let policy = RetryPolicy::default();
+        let mut backoff = policy.start(Instant::now());
+        let result = retry(&mut backoff, "scan", || async {
+            Err::<(), _>(RusotoError::Service(error("failed".to_string())))
+        })
+        .await;
+        result.unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn reports_the_last_error_when_giving_up() {
+        let throttled = give_up_on(ScanError::ProvisionedThroughputExceeded).await;
+        assert_eq!(throttled.status_code(), 429);
+        let failed = give_up_on(ScanError::InternalServerError).await;
+        assert_eq!(failed.status_code(), 503);
+    }
+}
@@ -0,0 +1,37 @@
+//! Setup shared by the handler tests in `src/bin`, which include this file with `#[path]`.
+#![allow(dead_code)]
+
+use ffxiv_item_name_database_api::repository::InMemoryItemRepository;
+use lambda_http::Response;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Items the handler tests run against.
+pub const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/items.json");
+
+pub fn repository() -> InMemoryItemRepository {
+    InMemoryItemRepository::from_file(FIXTURE).unwrap()
+}
+
+pub fn query(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+/// IDs of `Results` when they are returned as a list.
+pub fn ids(body: &Value) -> Vec<u64> {
+    body["Results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| result["ID"].as_u64().unwrap())
+        .collect()
+}
+
+/// The status and the parsed JSON body of `response`.
+pub fn parse(response: Response<String>) -> (u16, Value) {
+    let body = serde_json::from_str(response.body()).unwrap();
+    (response.status().as_u16(), body)
+}